@@ -0,0 +1,72 @@
+//! Throughput of sending and receiving messages over a socket pair.
+//!
+//! Run with `cargo bench --bench wire`.
+
+use reis::{ei, eis, PendingRequestResult};
+use std::{
+    hint::black_box,
+    os::unix::net::UnixStream,
+    time::{Duration, Instant},
+};
+
+const BATCH: usize = 512;
+const MESSAGES: usize = 2_000_000;
+
+fn contexts() -> (ei::Context, eis::Context) {
+    let (a, b) = UnixStream::pair().unwrap();
+    (ei::Context::new(a).unwrap(), eis::Context::new(b).unwrap())
+}
+
+// Sends `MESSAGES` messages from client to server in batches, reading and parsing each
+// batch on the server side.
+fn run(name: &str, send: impl Fn(&ei::handshake::Handshake)) {
+    let (client, server) = contexts();
+    let handshake = client.handshake();
+
+    let start = Instant::now();
+    let mut received = 0;
+    while received < MESSAGES {
+        for _ in 0..BATCH {
+            send(&handshake);
+        }
+        client.flush().unwrap();
+        while client.has_pending_writes() {
+            server.read().unwrap();
+            received += drain(&server);
+            client.flush().unwrap();
+        }
+        server.read().unwrap();
+        received += drain(&server);
+    }
+    report(name, received, start.elapsed());
+}
+
+fn drain(server: &eis::Context) -> usize {
+    let mut count = 0;
+    while let Some(result) = server.pending_request() {
+        match result {
+            PendingRequestResult::Request(request) => {
+                black_box(request);
+            }
+            PendingRequestResult::ParseError(err) => panic!("{err}"),
+            PendingRequestResult::InvalidObject(id) => panic!("invalid object {id}"),
+        }
+        count += 1;
+    }
+    count
+}
+
+fn report(name: &str, messages: usize, elapsed: Duration) {
+    let per_message = elapsed / u32::try_from(messages).unwrap();
+    #[allow(clippy::cast_precision_loss)]
+    let rate = messages as f64 / elapsed.as_secs_f64() / 1_000_000.0;
+    println!("{name:<16} {per_message:>10.2?}/message {rate:>8.2} M messages/s");
+}
+
+fn main() {
+    // Same size as pointer motion and button events
+    run("small", |handshake| handshake.handshake_version(1));
+    run("string", |handshake| {
+        handshake.name("an input capture client with a longer name");
+    });
+}
@@ -141,7 +141,8 @@ impl State {
     fn handle_new_connection(&mut self, context: eis::Context) -> io::Result<calloop::PostAction> {
         println!("New connection: {context:?}");
 
-        let source = EisRequestSource::new(context, 1);
+        let source = EisRequestSource::new(context, 1)
+            .with_handshake_error_handler(|err| eprintln!("Client handshake failed: {err}"));
         let mut context_state = ContextState::default();
         self.handle
             .insert_source(source, move |event, connected_state, _state| {
@@ -168,7 +169,7 @@ impl State {
         event: EisRequestSourceEvent,
     ) -> calloop::PostAction {
         match event {
-            EisRequestSourceEvent::Connected => {
+            EisRequestSourceEvent::Connected { .. } => {
                 let seat = connection.add_seat(
                     Some("default"),
                     DeviceCapability::Pointer
@@ -188,6 +189,17 @@ impl State {
                     return res;
                 }
             }
+            EisRequestSourceEvent::Disconnected {
+                initiator,
+                reason,
+                error,
+            } => {
+                println!("Client disconnected by {initiator:?} with reason {reason:?}");
+                if let Some(err) = error {
+                    eprintln!("Error communicating with client: {err}");
+                }
+                return calloop::PostAction::Remove;
+            }
         }
 
         let _ = connection.flush();
@@ -203,9 +215,10 @@ fn main() {
     let listener = eis::Listener::bind_auto().unwrap();
     let listener_source = EisListenerSource::new(listener);
     handle
-        .insert_source(listener_source, |context, (), state: &mut State| {
-            state.handle_new_connection(context)
-        })
+        .insert_source(
+            listener_source,
+            |context, _peer_credentials, state: &mut State| state.handle_new_connection(context),
+        )
         .unwrap();
 
     let terminate = Arc::new(AtomicBool::new(false));
@@ -8,22 +8,14 @@ use ashpd::desktop::{
 };
 use calloop::generic::Generic;
 use enumflags2::BitFlags;
-use once_cell::sync::Lazy;
-use reis::{ei, PendingRequestResult};
+use reis::{
+    ei,
+    handshake::{EiHandshaker, InterfaceVersions},
+    PendingRequestResult,
+};
 use std::{collections::HashMap, io, os::unix::net::UnixStream};
 use xkbcommon::xkb;
 
-static INTERFACES: Lazy<HashMap<&'static str, u32>> = Lazy::new(|| {
-    let mut m = HashMap::new();
-    m.insert("ei_callback", 1);
-    m.insert("ei_connection", 1);
-    m.insert("ei_seat", 1);
-    m.insert("ei_device", 1);
-    m.insert("ei_pingpong", 1);
-    m.insert("ei_keyboard", 1);
-    m
-});
-
 #[derive(Debug, Default)]
 struct SeatData {
     name: Option<String>,
@@ -51,6 +43,7 @@ struct State {
     running: bool,
     sequence: u32,
     last_serial: u32,
+    handshaker: EiHandshaker<'static>,
     keymap: Option<xkb::Keymap>,
 }
 
@@ -76,24 +69,11 @@ impl State {
                 }
             };
             match request {
-                ei::Event::Handshake(handshake, request) => match request {
-                    ei::handshake::Event::HandshakeVersion { version: _ } => {
-                        handshake.handshake_version(1);
-                        handshake.name("type-text-example");
-                        handshake.context_type(ei::handshake::ContextType::Sender);
-                        for (interface, version) in INTERFACES.iter() {
-                            handshake.interface_version(interface, *version);
-                        }
-                        handshake.finish();
-                    }
-                    ei::handshake::Event::Connection {
-                        connection: _,
-                        serial,
-                    } => {
-                        self.last_serial = serial;
+                event @ ei::Event::Handshake(..) => {
+                    if let Some(resp) = self.handshaker.handle_event(event).unwrap() {
+                        self.last_serial = resp.serial;
                     }
-                    _ => {}
-                },
+                }
                 ei::Event::Connection(_connection, request) => match request {
                     ei::connection::Event::Seat { seat } => {
                         self.seats.insert(seat, SeatData::default());
@@ -280,6 +260,15 @@ fn main() {
         devices: HashMap::new(),
         running: true,
         last_serial: u32::MAX,
+        handshaker: EiHandshaker::new("type-text-example", ei::handshake::ContextType::Sender)
+            .with_interfaces(InterfaceVersions::from_iter([
+                ("ei_callback", 1),
+                ("ei_connection", 1),
+                ("ei_seat", 1),
+                ("ei_device", 1),
+                ("ei_pingpong", 1),
+                ("ei_keyboard", 1),
+            ])),
         sequence: 0,
         keymap: None,
     };
@@ -0,0 +1,94 @@
+// Records of a capture, in the JSON lines format documented in `reis::capture`.
+
+use std::{
+    fs,
+    io::{self, BufRead},
+};
+
+use reis::capture::Side as Sender;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Ei,
+    Eis,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+// `interface` isn't read, since the decoder tracks the objects itself
+#[derive(Debug, Deserialize)]
+pub struct Record {
+    pub timestamp_us: u64,
+    pub side: Side,
+    pub direction: Direction,
+    pub object_id: u64,
+    pub length: u32,
+    pub opcode: u32,
+    pub payload: String,
+    pub fds: usize,
+}
+
+impl Record {
+    // Side that sent the message
+    pub fn sender(&self) -> Sender {
+        match (self.side, self.direction) {
+            (Side::Ei, Direction::Out) | (Side::Eis, Direction::In) => Sender::Client,
+            (Side::Ei, Direction::In) | (Side::Eis, Direction::Out) => Sender::Server,
+        }
+    }
+
+    // Header and payload, as sent on the wire
+    pub fn message(&self) -> io::Result<Vec<u8>> {
+        let payload = self.payload.as_bytes().chunks_exact(2);
+        if !payload.remainder().is_empty() {
+            return Err(invalid_data("payload has odd length"));
+        }
+        // Otherwise the decoder would lose track of where later messages start
+        if self.length as usize != 16 + payload.len() {
+            return Err(invalid_data("length does not match payload"));
+        }
+        let mut message = Vec::with_capacity(16 + payload.len());
+        message.extend_from_slice(&self.object_id.to_ne_bytes());
+        message.extend_from_slice(&self.length.to_ne_bytes());
+        message.extend_from_slice(&self.opcode.to_ne_bytes());
+        for pair in payload {
+            let byte = std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| invalid_data("payload is not hexadecimal"))?;
+            message.push(byte);
+        }
+        Ok(message)
+    }
+}
+
+// Reads a capture from `path`, or stdin if it is `-`
+pub fn read(path: &str) -> io::Result<Vec<Record>> {
+    let reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(io::BufReader::new(fs::File::open(path)?))
+    };
+    let mut records = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .map_err(|err| invalid_data(&format!("line {}: {err}", i + 1)))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
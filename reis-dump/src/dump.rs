@@ -0,0 +1,214 @@
+// Decoding captures, and printing their messages and objects.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    os::unix::io::OwnedFd,
+};
+
+use reis::{
+    capture::{Decoded, DecodedMessage, Decoder, Side as Sender},
+    ei, eis, PendingRequestResult,
+};
+
+use crate::capture::Record;
+
+// Decodes each record in order, calling `f` with the result
+fn decode(records: &[Record], mut f: impl FnMut(&Record, Result<DecodedMessage, String>)) {
+    let decoder = Decoder::new();
+    for record in records {
+        let message = match record.message() {
+            Ok(message) => message,
+            Err(err) => {
+                f(record, Err(err.to_string()));
+                continue;
+            }
+        };
+        // Fds aren't captured, so substitute something that can be parsed as one
+        let fds =
+            (0..record.fds).filter_map(|_| fs::File::open("/dev/null").ok().map(OwnedFd::from));
+        decoder.receive(record.sender(), &message, fds);
+        let result = match decoder.pending(record.sender()) {
+            Some(PendingRequestResult::Request(message)) => Ok(message),
+            Some(PendingRequestResult::ParseError(err)) => Err(format!("parse error: {err}")),
+            Some(PendingRequestResult::InvalidObject(id)) => Err(format!("invalid object {id:x}")),
+            None => Err(format!("truncated message of length {}", record.length)),
+        };
+        f(record, result);
+    }
+}
+
+pub fn print(out: &mut impl Write, records: &[Record], interfaces: &[String]) -> io::Result<()> {
+    let start = records.first().map_or(0, |record| record.timestamp_us);
+    let mut status = Ok(());
+    decode(records, |record, result| {
+        if status.is_err() {
+            return;
+        }
+        let time = record.timestamp_us.saturating_sub(start) as f64 / 1000.0;
+        let arrow = match record.sender() {
+            Sender::Client => " -> ",
+            Sender::Server => "",
+        };
+        match result {
+            Ok(message) => {
+                if interfaces.is_empty() || interfaces.contains(&message.interface) {
+                    status = writeln!(
+                        out,
+                        "[{time:10.3}] {arrow}{}@{:x}.{}({})",
+                        message.interface,
+                        message.object_id,
+                        message.name,
+                        message.format_args()
+                    );
+                }
+            }
+            Err(err) => {
+                if interfaces.is_empty() {
+                    status = writeln!(
+                        out,
+                        "[{time:10.3}] {arrow}@{:x} opcode {}: {err}",
+                        record.object_id, record.opcode
+                    );
+                }
+            }
+        }
+    });
+    status
+}
+
+struct Node {
+    interface: String,
+    version: u32,
+    children: Vec<u64>,
+    details: Vec<String>,
+    destroyed: bool,
+}
+
+impl Node {
+    fn new(interface: String, version: u32) -> Self {
+        Self {
+            interface,
+            version,
+            children: Vec::new(),
+            details: Vec::new(),
+            destroyed: false,
+        }
+    }
+}
+
+pub fn tree(out: &mut impl Write, records: &[Record]) -> io::Result<()> {
+    let mut nodes = HashMap::new();
+    nodes.insert(0, Node::new("ei_handshake".to_owned(), 1));
+    let mut errors = 0;
+    decode(records, |_, result| {
+        let Ok(message) = result else {
+            errors += 1;
+            return;
+        };
+        for object in &message.new_objects {
+            nodes.insert(
+                object.id(),
+                Node::new(object.interface().to_owned(), object.version()),
+            );
+            if let Some(parent) = nodes.get_mut(&message.object_id) {
+                parent.children.push(object.id());
+            }
+        }
+        if let Some(node) = nodes.get_mut(&message.object_id) {
+            if message.name == "destroyed" {
+                node.destroyed = true;
+            }
+            if let Some(detail) = detail(&message.message) {
+                node.details.push(detail);
+            }
+        }
+    });
+
+    print_node(out, &nodes, 0, "", "")?;
+    if errors != 0 {
+        writeln!(out, "({errors} messages could not be decoded)")?;
+    }
+    Ok(())
+}
+
+// Describes messages that say something about their object
+fn detail(message: &Decoded) -> Option<String> {
+    Some(match message {
+        Decoded::Request(eis::Request::Handshake(_, request)) => match request {
+            eis::handshake::Request::Name { name } => format!("name {name:?}"),
+            eis::handshake::Request::ContextType { context_type } => {
+                format!("context type {context_type:?}")
+            }
+            _ => return None,
+        },
+        Decoded::Event(ei::Event::Connection(
+            _,
+            ei::connection::Event::Disconnected {
+                reason,
+                explanation,
+                ..
+            },
+        )) => format!("disconnected: {reason:?} {explanation:?}"),
+        Decoded::Event(ei::Event::Seat(_, event)) => match event {
+            ei::seat::Event::Name { name } => format!("name {name:?}"),
+            ei::seat::Event::Capability { interface, .. } => format!("capability {interface}"),
+            _ => return None,
+        },
+        Decoded::Event(ei::Event::Device(_, event)) => match event {
+            ei::device::Event::Name { name } => format!("name {name:?}"),
+            ei::device::Event::DeviceType { device_type } => format!("type {device_type:?}"),
+            ei::device::Event::Dimensions { width, height } => {
+                format!("dimensions {width}x{height}")
+            }
+            ei::device::Event::Region {
+                offset_x,
+                offset_y,
+                width,
+                hight,
+                scale,
+            } => format!("region {width}x{hight}+{offset_x}+{offset_y} scale {scale}"),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+fn print_node(
+    out: &mut impl Write,
+    nodes: &HashMap<u64, Node>,
+    id: u64,
+    prefix: &str,
+    child_prefix: &str,
+) -> io::Result<()> {
+    let node = &nodes[&id];
+    write!(out, "{prefix}{}@{id:x} v{}", node.interface, node.version)?;
+    if node.destroyed {
+        write!(out, " (destroyed)")?;
+    }
+    writeln!(out)?;
+    let detail_prefix = if node.children.is_empty() {
+        "  "
+    } else {
+        "│ "
+    };
+    for detail in &node.details {
+        writeln!(out, "{child_prefix}{detail_prefix}{detail}")?;
+    }
+    for (i, child) in node.children.iter().enumerate() {
+        let (branch, indent) = if i == node.children.len() - 1 {
+            ("└─", "  ")
+        } else {
+            ("├─", "│ ")
+        };
+        print_node(
+            out,
+            nodes,
+            *child,
+            &format!("{child_prefix}{branch}"),
+            &format!("{child_prefix}{indent}"),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// Records and dissects captures of EI connections, in the format of `reis::capture`.
+
+use std::{env, io, path::PathBuf, process};
+
+mod capture;
+mod dump;
+mod record;
+
+const USAGE: &str = "\
+Usage:
+  reis-dump record --listen <socket> --upstream <socket> [--output <file>]
+  reis-dump print [--interface <name>]... <capture>
+  reis-dump tree <capture>
+
+Commands:
+  record  Listen for a client on <socket>, forward its connection to the EIS
+          implementation at <upstream>, and write a capture of the messages
+          to <file>, or stdout. Socket paths are relative to $XDG_RUNTIME_DIR.
+  print   Print the messages of a capture, like WAYLAND_DEBUG. Requests sent by
+          the client are marked with `->`.
+  tree    Print the objects created on the connection, like seats and devices.
+
+A capture of `-` is read from stdin.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("record") => record(&args[1..]),
+        Some("print") => print(&args[1..]),
+        Some("tree") => tree(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return;
+        }
+        _ => usage(),
+    };
+    match result {
+        // Output piped to something like `head`
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("reis-dump: {err}");
+            process::exit(1);
+        }
+        Ok(()) => {}
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
+
+fn record(args: &[String]) -> io::Result<()> {
+    let mut listen = None;
+    let mut upstream = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = Some(socket_path(value)),
+            "--upstream" => upstream = Some(socket_path(value)),
+            "--output" | "-o" => output = Some(PathBuf::from(value)),
+            _ => usage(),
+        }
+    }
+    let (Some(listen), Some(upstream)) = (listen, upstream) else {
+        usage();
+    };
+    record::run(&listen, &upstream, output.as_deref())
+}
+
+fn print(args: &[String]) -> io::Result<()> {
+    let mut interfaces = Vec::new();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interface" | "-i" => interfaces.push(args.next().unwrap_or_else(|| usage()).clone()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let records = capture::read(path.unwrap_or_else(|| usage()))?;
+    dump::print(&mut io::stdout().lock(), &records, &interfaces)
+}
+
+fn tree(args: &[String]) -> io::Result<()> {
+    let [path] = args else {
+        usage();
+    };
+    let records = capture::read(path)?;
+    dump::tree(&mut io::stdout().lock(), &records)
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    reis_proxy::socket_path(name).unwrap_or_else(|err| {
+        eprintln!("reis-dump: {err}");
+        process::exit(1);
+    })
+}
@@ -0,0 +1,26 @@
+// Socket-forwarding shim recording a connection, from the perspective of the EIS
+// implementation.
+
+use std::{fs, io, os::unix::net::UnixListener, path::Path};
+
+use reis::capture::CaptureRecorder;
+
+pub fn run(listen: &Path, upstream: &Path, output: Option<&Path>) -> io::Result<()> {
+    let recorder = match output {
+        Some(path) => CaptureRecorder::create(path)?,
+        None => CaptureRecorder::new(io::stdout()),
+    };
+
+    let listener = UnixListener::bind(listen)?;
+    eprintln!("reis-dump: listening on {}", listen.display());
+    let accepted = listener.accept();
+    drop(listener);
+    let _ = fs::remove_file(listen);
+    let (client, _) = accepted?;
+    eprintln!("reis-dump: client connected");
+
+    let options = reis_proxy::Options::default();
+    reis_proxy::run(1, client, upstream, &options, Some(recorder))?;
+    eprintln!("reis-dump: connection closed");
+    Ok(())
+}
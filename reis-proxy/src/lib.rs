@@ -0,0 +1,28 @@
+// Forwarding EI connections between clients and an EIS implementation, decoding and optionally
+// recording or disrupting the messages. Used by `reis-proxy`, and by `reis-dump record`.
+
+use std::{env, io, path::PathBuf, time::Duration};
+
+mod proxy;
+
+pub use proxy::run;
+
+// Faults and logging applied to each connection
+#[derive(Debug, Default)]
+pub struct Options {
+    pub log: bool,
+    pub drop_frames: Option<u32>,
+    pub delay: Option<Duration>,
+    pub invalid_object: Option<u32>,
+}
+
+// Same lookup as `reis::ei::Context::connect_to_env`
+pub fn socket_path(name: &str) -> io::Result<PathBuf> {
+    let path = PathBuf::from(name);
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR not set"))?;
+    Ok(PathBuf::from(runtime_dir).join(path))
+}
@@ -0,0 +1,118 @@
+// Proxy between EI clients and an EIS implementation, decoding and optionally recording or
+// disrupting the messages it forwards.
+
+use std::{
+    env, os::unix::net::UnixListener, path::PathBuf, process, sync::Arc, thread, time::Duration,
+};
+
+use reis::capture::CaptureRecorder;
+use reis_proxy::Options;
+
+const USAGE: &str = "\
+Usage: reis-proxy --listen <socket> --upstream <socket> [options]
+
+Accepts clients on <socket>, and forwards each connection to the EIS
+implementation at <upstream>. Socket paths are relative to $XDG_RUNTIME_DIR.
+
+Options:
+  --log                  Print each message to stderr, like WAYLAND_DEBUG.
+                         Requests sent by clients are marked with `->`.
+  --record <prefix>      Write a capture of the nth connection to
+                         <prefix>-<n>.jsonl, for `reis-dump`.
+
+Faults:
+  --drop-frames <n>      Drop every nth `ei_device.frame` message.
+  --delay <ms>           Delay each message by <ms> milliseconds.
+  --invalid-object <n>   After every nth message following the handshake, also
+                         send a message for an object that doesn't exist.";
+
+fn main() {
+    let mut listen = None;
+    let mut upstream = None;
+    let mut record = None;
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--listen" => listen = Some(socket_path(&value())),
+            "--upstream" => upstream = Some(socket_path(&value())),
+            "--log" => options.log = true,
+            "--record" => record = Some(PathBuf::from(value())),
+            "--drop-frames" => options.drop_frames = Some(count(&value())),
+            "--delay" => options.delay = Some(Duration::from_millis(count(&value()).into())),
+            "--invalid-object" => options.invalid_object = Some(count(&value())),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => usage(),
+        }
+    }
+    let (Some(listen), Some(upstream)) = (listen, upstream) else {
+        usage();
+    };
+
+    let listener = UnixListener::bind(&listen).unwrap_or_else(|err| {
+        eprintln!(
+            "reis-proxy: failed to listen on {}: {err}",
+            listen.display()
+        );
+        process::exit(1);
+    });
+    eprintln!("reis-proxy: listening on {}", listen.display());
+    let options = Arc::new(options);
+    for (n, client) in (1..).zip(listener.incoming()) {
+        let client = match client {
+            Ok(client) => client,
+            Err(err) => {
+                eprintln!("reis-proxy: failed to accept connection: {err}");
+                continue;
+            }
+        };
+        eprintln!("reis-proxy: connection {n} opened");
+        let recorder = match &record {
+            Some(prefix) => {
+                let path = format!("{}-{n}.jsonl", prefix.display());
+                match CaptureRecorder::create(&path) {
+                    Ok(recorder) => Some(recorder),
+                    Err(err) => {
+                        eprintln!("reis-proxy: failed to create {path}: {err}");
+                        continue;
+                    }
+                }
+            }
+            None => None,
+        };
+        let upstream = upstream.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+            match reis_proxy::run(n, client, &upstream, &options, recorder) {
+                Ok(()) => eprintln!("reis-proxy: connection {n} closed"),
+                Err(err) => eprintln!("reis-proxy: connection {n} closed: {err}"),
+            };
+        });
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    process::exit(2);
+}
+
+fn count(value: &str) -> u32 {
+    match value.parse() {
+        Ok(count) if count > 0 => count,
+        _ => {
+            eprintln!("reis-proxy: expected a positive number, got `{value}`");
+            process::exit(2);
+        }
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    reis_proxy::socket_path(name).unwrap_or_else(|err| {
+        eprintln!("reis-proxy: {err}");
+        process::exit(1);
+    })
+}
@@ -0,0 +1,237 @@
+// Forwarding the messages of one connection, in both directions.
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::Shutdown,
+    os::unix::{
+        io::{AsFd, OwnedFd},
+        net::UnixStream,
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use reis::{
+    capture::{CaptureRecorder, DecodedMessage, Decoder, Side},
+    PendingRequestResult, Transport,
+};
+
+use crate::Options;
+
+// Object ID used for injected messages. The client never gets this far creating objects, and
+// the server never uses IDs in this range.
+const INVALID_OBJECT_ID: u64 = 0xdead_beef;
+
+struct Connection<'a> {
+    n: u32,
+    options: &'a Options,
+    decoder: Decoder,
+    recorder: Option<Mutex<CaptureRecorder>>,
+    // Set once the server has sent `ei_handshake.connection`. Messages for unknown objects
+    // are only injected after this, since they would fail the handshake.
+    connected: AtomicBool,
+}
+
+// Messages since the last fault of each kind, per direction
+#[derive(Default)]
+struct Faults {
+    messages: u32,
+    frames: u32,
+}
+
+// Forwards the connection of `client` to the EIS implementation at `upstream`, until either
+// side closes it. The messages are recorded with `recorder`, if any.
+pub fn run(
+    n: u32,
+    client: UnixStream,
+    upstream: &Path,
+    options: &Options,
+    recorder: Option<CaptureRecorder>,
+) -> io::Result<()> {
+    let server = UnixStream::connect(upstream)?;
+    let connection = Connection {
+        n,
+        options,
+        decoder: Decoder::new(),
+        recorder: recorder.map(Mutex::new),
+        connected: AtomicBool::new(false),
+    };
+
+    thread::scope(|scope| {
+        let requests = scope.spawn(|| connection.forward(&client, &server, Side::Client));
+        let events = connection.forward(&server, &client, Side::Server);
+        let requests = requests.join().unwrap();
+        events.and(requests)
+    })
+}
+
+impl Connection<'_> {
+    // Forwards everything `sender` sends on `from` to `to`, one message at a time.
+    fn forward(&self, from: &UnixStream, to: &UnixStream, sender: Side) -> io::Result<()> {
+        let result = self.forward_inner(from, to, sender);
+        // Let the other direction finish too
+        let _ = from.shutdown(Shutdown::Read);
+        let _ = to.shutdown(Shutdown::Write);
+        result
+    }
+
+    fn forward_inner(&self, from: &UnixStream, to: &UnixStream, sender: Side) -> io::Result<()> {
+        let mut buf = vec![0; 16384];
+        let mut received_fds = Vec::new();
+        let mut fds = VecDeque::new();
+        let mut pending = Vec::new();
+        let mut faults = Faults::default();
+        loop {
+            let count = from.recv(&mut buf, &mut received_fds)?;
+            if count == 0 {
+                return Ok(());
+            }
+            // The decoder takes the fds of the messages it parses, and the originals are
+            // forwarded.
+            let copies = received_fds
+                .iter()
+                .map(OwnedFd::try_clone)
+                .collect::<io::Result<Vec<_>>>()?;
+            self.decoder.receive(sender, &buf[..count], copies);
+            fds.extend(received_fds.drain(..));
+
+            pending.extend_from_slice(&buf[..count]);
+            let mut start = 0;
+            while pending.len() - start >= 16 {
+                let length = u32::from_ne_bytes(pending[start + 8..start + 12].try_into().unwrap());
+                let length = length as usize;
+                if length < 16 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid message length {length}"),
+                    ));
+                }
+                if pending.len() - start < length {
+                    break;
+                }
+                let message = &pending[start..start + length];
+                self.handle_message(to, sender, message, &mut fds, &mut faults)?;
+                start += length;
+            }
+            pending.drain(..start);
+        }
+    }
+
+    fn handle_message(
+        &self,
+        to: &UnixStream,
+        sender: Side,
+        message: &[u8],
+        fds: &mut VecDeque<OwnedFd>,
+        faults: &mut Faults,
+    ) -> io::Result<()> {
+        let decoded = self.decoder.pending(sender);
+        let decoded = match decoded {
+            Some(PendingRequestResult::Request(decoded)) => Ok(decoded),
+            Some(PendingRequestResult::ParseError(err)) => Err(format!("parse error: {err}")),
+            Some(PendingRequestResult::InvalidObject(id)) => Err(format!("invalid object {id:x}")),
+            None => Err("not decoded".to_owned()),
+        };
+        // If the message can't be decoded, there's no telling which fds belong to it.
+        let fd_count = decoded
+            .as_ref()
+            .map_or(fds.len(), |decoded| decoded.fds.min(fds.len()));
+        let message_fds: Vec<_> = fds.drain(..fd_count).collect();
+
+        if self.options.log {
+            self.log(sender, message, &decoded);
+        }
+        if let Some(recorder) = &self.recorder {
+            let interface = decoded.as_ref().ok().map(|decoded| &*decoded.interface);
+            recorder
+                .lock()
+                .unwrap()
+                .record_message(sender, message, interface, fd_count);
+        }
+
+        if let (Some(n), Ok(decoded)) = (self.options.drop_frames, &decoded) {
+            if decoded.interface == "ei_device" && decoded.name == "frame" {
+                faults.frames += 1;
+                if faults.frames == n {
+                    faults.frames = 0;
+                    self.note("dropped frame");
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(delay) = self.options.delay {
+            thread::sleep(delay);
+        }
+        send_all(to, message, &message_fds)?;
+
+        if let Ok(decoded) = &decoded {
+            if decoded.interface == "ei_handshake" && decoded.name == "connection" {
+                self.connected.store(true, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+        if !self.connected.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(n) = self.options.invalid_object {
+            faults.messages += 1;
+            if faults.messages == n {
+                faults.messages = 0;
+                let mut invalid = Vec::with_capacity(16);
+                invalid.extend_from_slice(&INVALID_OBJECT_ID.to_ne_bytes());
+                invalid.extend_from_slice(&16u32.to_ne_bytes());
+                invalid.extend_from_slice(&0u32.to_ne_bytes());
+                send_all(to, &invalid, &[])?;
+                self.note(&format!(
+                    "sent message for invalid object {INVALID_OBJECT_ID:x}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn log(&self, sender: Side, message: &[u8], decoded: &Result<DecodedMessage, String>) {
+        let arrow = match sender {
+            Side::Client => " -> ",
+            Side::Server => "",
+        };
+        match decoded {
+            Ok(decoded) => eprintln!(
+                "[{}] {arrow}{}@{:x}.{}({})",
+                self.n,
+                decoded.interface,
+                decoded.object_id,
+                decoded.name,
+                decoded.format_args()
+            ),
+            Err(err) => {
+                let object_id = u64::from_ne_bytes(message[0..8].try_into().unwrap());
+                let opcode = u32::from_ne_bytes(message[12..16].try_into().unwrap());
+                eprintln!("[{}] {arrow}@{object_id:x} opcode {opcode}: {err}", self.n);
+            }
+        }
+    }
+
+    fn note(&self, note: &str) {
+        if self.options.log {
+            eprintln!("[{}] ({note})", self.n);
+        }
+    }
+}
+
+fn send_all(socket: &UnixStream, mut bytes: &[u8], fds: &[OwnedFd]) -> io::Result<()> {
+    let fds: Vec<_> = fds.iter().map(AsFd::as_fd).collect();
+    // Fds are sent along with the first bytes
+    let mut fds = &fds[..];
+    while !bytes.is_empty() {
+        let count = socket.send(bytes, fds)?;
+        bytes = &bytes[count..];
+        fds = &[];
+    }
+    Ok(())
+}
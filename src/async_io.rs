@@ -1,18 +1,42 @@
 //! Module containing [`async_io`] event streams.
 //!
-use async_io::Async;
-use futures_util::{Stream, StreamExt};
+use async_io::{Async, Timer};
+use futures_util::{
+    future::{self, Either},
+    Stream, StreamExt,
+};
 use std::{
     io,
-    pin::Pin,
+    pin::{pin, Pin},
     task::{ready, Context, Poll},
+    time::Duration,
+};
+
+pub use crate::async_shared::AuthorizationHandle;
+pub use crate::handshake::{
+    EiHandshaker, EisHandshakeResp, EisHandshaker, HandshakeError, HandshakeResp,
 };
+use crate::{async_shared, ei, eis, request, Error, PendingRequestResult};
 
-pub use crate::handshake::{HandshakeError, HandshakeResp};
-use crate::{async_shared, ei, Error, PendingRequestResult};
+// Sends messages that a flush left buffered because the socket was full, once it is writable.
+fn poll_flush_pending<T>(
+    fd: &Async<T>,
+    context: &mut Context<'_>,
+    has_pending_writes: fn(&T) -> bool,
+    flush: fn(&T) -> rustix::io::Result<()>,
+) -> io::Result<()> {
+    while has_pending_writes(fd.get_ref()) {
+        let Poll::Ready(res) = fd.poll_writable(context) else {
+            break;
+        };
+        res?;
+        flush(fd.get_ref())?;
+    }
+    Ok(())
+}
 
 /// Stream of `ei::Event`s.
-pub struct EiEventStream(Async<ei::Context>);
+pub struct EiEventStream(Async<ei::Context>, async_shared::TaskWaker);
 
 impl EiEventStream {
     /// Creates a new event stream.
@@ -21,7 +45,8 @@ impl EiEventStream {
     ///
     /// Will return `Err` if the underlying async file descriptor registration fails.
     pub fn new(context: ei::Context) -> io::Result<Self> {
-        Async::new(context).map(Self)
+        let waker = async_shared::TaskWaker::new(&context.0);
+        Async::new(context).map(|fd| Self(fd, waker))
     }
 }
 
@@ -32,6 +57,15 @@ impl Stream for EiEventStream {
         self: Pin<&mut Self>,
         context: &mut Context<'_>,
     ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.1.register(context.waker());
+        if let Err(err) = poll_flush_pending(
+            &self.0,
+            context,
+            ei::Context::has_pending_writes,
+            ei::Context::flush,
+        ) {
+            return Poll::Ready(Some(Err(err)));
+        }
         // If we already have a pending event, return that
         if let Some(res) = async_shared::poll_pending_event(self.0.get_ref()) {
             return res;
@@ -77,6 +111,27 @@ pub async fn ei_handshake(
     async_shared::ei_handshake(events, name, context_type).await
 }
 
+/// Executes the handshake in async mode, using the given handshaker.
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+/// out.
+pub async fn ei_handshake_with(
+    events: &mut EiEventStream,
+    handshaker: EiHandshaker<'_>,
+) -> Result<HandshakeResp, Error> {
+    if let Some(timeout) = handshaker.timeout() {
+        let handshake = pin!(async_shared::ei_handshake_with(events, handshaker));
+        match future::select(handshake, Timer::after(timeout)).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => Err(HandshakeError::Timeout.into()),
+        }
+    } else {
+        async_shared::ei_handshake_with(events, handshaker).await
+    }
+}
+
 impl ei::Context {
     /// Executes the handshake in async mode.
     ///
@@ -97,3 +152,243 @@ impl ei::Context {
         Ok((connection, stream))
     }
 }
+
+/// Stream of `eis::Context`s accepted by an [`eis::Listener`].
+pub struct EisListenerStream(Async<eis::Listener>);
+
+impl EisListenerStream {
+    /// Creates a new listener stream.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the underlying async file descriptor registration fails.
+    pub fn new(listener: eis::Listener) -> io::Result<Self> {
+        Async::new(listener).map(Self)
+    }
+}
+
+impl Stream for EisListenerStream {
+    type Item = io::Result<eis::Context>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        loop {
+            if let Err(err) = ready!(self.0.poll_readable(context)) {
+                return Poll::Ready(Some(Err(err)));
+            }
+            match self.0.get_ref().accept() {
+                Ok(Some(context)) => return Poll::Ready(Some(Ok(context))),
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+/// Stream of `eis::Request`s.
+pub struct EisRequestStream(Async<eis::Context>, async_shared::TaskWaker);
+
+impl EisRequestStream {
+    /// Creates a new request stream.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the underlying async file descriptor registration fails.
+    pub fn new(context: eis::Context) -> io::Result<Self> {
+        let waker = async_shared::TaskWaker::new(&context.0);
+        Async::new(context).map(|fd| Self(fd, waker))
+    }
+}
+
+impl Stream for EisRequestStream {
+    type Item = io::Result<PendingRequestResult<eis::Request>>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.1.register(context.waker());
+        if let Err(err) = poll_flush_pending(
+            &self.0,
+            context,
+            eis::Context::has_pending_writes,
+            eis::Context::flush,
+        ) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        // If we already have a pending request, return that
+        if let Some(res) = async_shared::poll_pending_request(self.0.get_ref()) {
+            return res;
+        }
+        if let Err(err) = ready!(self.0.poll_readable(context)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        match self.0.get_ref().read() {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Poll::Ready(None),
+            Err(err) => Poll::Ready(Some(Err(err))),
+            Ok(_) => {
+                // `Backend::read()` reads until `WouldBlock`, EOF, or error
+                async_shared::poll_pending_request(self.0.get_ref()).unwrap_or(Poll::Pending)
+            }
+        }
+    }
+}
+
+impl async_shared::KeepaliveTimer for Timer {
+    fn new(after: Duration) -> Self {
+        Timer::after(after)
+    }
+
+    fn reset(&mut self, after: Duration) {
+        self.set_after(after);
+    }
+
+    fn poll_expired(&mut self, context: &mut Context<'_>) -> Poll<()> {
+        match self.poll_next_unpin(context) {
+            Poll::Ready(Some(_)) => Poll::Ready(()),
+            // Expired one-shot timer, until it is reset
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// EIS convert request stream.
+///
+/// If an error occurs, it is returned after sending a protocol error to the client, and the
+/// stream ends.
+pub struct EisConvertRequestStream(async_shared::EisConvertRequestStream<EisRequestStream, Timer>);
+
+impl EisConvertRequestStream {
+    /// Periodically checks that the client is still responsive, like
+    /// `EisRequestSource::with_keepalive` of the `calloop` feature.
+    ///
+    /// The client is pinged with [`Connection::ping`](request::Connection::ping). If it doesn't
+    /// answer within `timeout`, it is disconnected with
+    /// [`DisconnectReason::Error`](eis::connection::DisconnectReason::Error), and the stream
+    /// ends with [`EisRequest::Disconnect`](request::EisRequest::Disconnect). After an answer,
+    /// the next ping is sent after `interval`. Pings are only sent while the stream is polled.
+    #[must_use]
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.0.keepalive = Some(async_shared::Keepalive::new(interval, timeout));
+        self
+    }
+}
+
+impl Stream for EisConvertRequestStream {
+    type Item = Result<request::EisRequest, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.0.poll_next_unpin(context)
+    }
+}
+
+/// Executes the server-side handshake in async mode, using the given handshaker.
+///
+/// If the authorizer returns [`Authorization::Defer`], the client is disconnected and
+/// [`HandshakeError::Deferred`] is returned. Use [`eis_handshake_with_authorization`] to resolve
+/// the decision later instead.
+///
+/// [`Authorization::Defer`]: crate::handshake::Authorization::Defer
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+/// out.
+pub async fn eis_handshake_with(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+) -> Result<EisHandshakeResp, Error> {
+    eis_handshake_inner(requests, handshaker, None).await
+}
+
+/// Executes the server-side handshake in async mode, waiting for `authorization` to be resolved
+/// if the authorizer returns [`Authorization::Defer`].
+///
+/// ```no_run
+/// # async fn f(context: reis::eis::Context) -> Result<(), reis::Error> {
+/// use reis::{
+///     async_io::{
+///         eis_handshake_with_authorization, AuthorizationHandle, EisHandshaker, EisRequestStream,
+///     },
+///     handshake::Authorization,
+/// };
+///
+/// let authorization = AuthorizationHandle::new();
+/// let mut handshaker = EisHandshaker::new(&context, 1);
+/// handshaker.set_authorizer({
+///     let authorization = authorization.clone();
+///     move |_info| {
+///         // Ask the user, and call `authorization.resolve()` with the answer
+///         # let _ = &authorization;
+///         Authorization::Defer
+///     }
+/// });
+/// let mut requests = EisRequestStream::new(context.clone())?;
+/// let resp = eis_handshake_with_authorization(&mut requests, handshaker, &authorization).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The time spent waiting counts towards the [timeout](EisHandshaker::set_timeout), if any.
+///
+/// [`Authorization::Defer`]: crate::handshake::Authorization::Defer
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, the client is rejected, or
+/// the handshake times out.
+pub async fn eis_handshake_with_authorization(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+    authorization: &AuthorizationHandle,
+) -> Result<EisHandshakeResp, Error> {
+    eis_handshake_inner(requests, handshaker, Some(authorization)).await
+}
+
+async fn eis_handshake_inner(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+    authorization: Option<&AuthorizationHandle>,
+) -> Result<EisHandshakeResp, Error> {
+    let context = requests.0.get_ref().clone();
+    if let Some(timeout) = handshaker.timeout() {
+        let handshake = pin!(async_shared::eis_handshake_with(
+            requests,
+            &context,
+            handshaker,
+            authorization
+        ));
+        match future::select(handshake, Timer::after(timeout)).await {
+            Either::Left((res, _)) => res,
+            Either::Right(_) => Err(HandshakeError::Timeout.into()),
+        }
+    } else {
+        async_shared::eis_handshake_with(requests, &context, handshaker, authorization).await
+    }
+}
+
+impl eis::Context {
+    /// Executes the server-side handshake in async mode.
+    ///
+    /// `handshaker` should be created for this context. See [`eis_handshake_with`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+    /// out.
+    pub async fn handshake_async_io(
+        &self,
+        handshaker: EisHandshaker,
+    ) -> Result<(request::Connection, EisConvertRequestStream), Error> {
+        let mut requests = EisRequestStream::new(self.clone())?;
+        let resp = eis_handshake_with(&mut requests, handshaker).await?;
+        let stream = async_shared::EisConvertRequestStream::new(requests, self, resp)?;
+        let connection = stream.converter.handle().clone();
+        Ok((connection, EisConvertRequestStream(stream)))
+    }
+}
@@ -1,14 +1,50 @@
 // Async code shared between tokio and async-io
 
-use futures_util::{Stream, StreamExt};
+use futures_util::{future::poll_fn, Stream, StreamExt};
 use std::{
     io,
     pin::Pin,
-    task::{ready, Context, Poll},
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll, Waker},
+    time::Duration,
 };
 
 pub use crate::handshake::HandshakeResp;
-use crate::{ei, handshake::EiHandshaker, Error, PendingRequestResult};
+use crate::{
+    ei, eis,
+    handshake::{Authorization, EiHandshaker, EisHandshakeResp, EisHandshaker, HandshakeError},
+    request::{self, EisRequest, EisRequestConverter},
+    wire::{Backend, WriteWaker},
+    Error, PendingRequestResult,
+};
+
+// Wakes the task polling a stream when a flush outside of it leaves messages buffered, so the
+// stream starts waiting for the socket to become writable right away.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TaskWaker(Arc<Mutex<Option<Waker>>>);
+
+impl TaskWaker {
+    pub(crate) fn new(backend: &Backend) -> Self {
+        let waker = Self::default();
+        let task = waker.clone();
+        backend.set_write_waker(Some(WriteWaker::new(move || {
+            if let Some(waker) = task.0.lock().unwrap().take() {
+                waker.wake();
+            }
+        })));
+        waker
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut current = self.0.lock().unwrap();
+        if !current
+            .as_ref()
+            .is_some_and(|current| current.will_wake(waker))
+        {
+            *current = Some(waker.clone());
+        }
+    }
+}
 
 pub(crate) fn poll_pending_event(
     context: &ei::Context,
@@ -16,6 +52,12 @@ pub(crate) fn poll_pending_event(
     Some(Poll::Ready(Some(Ok(context.pending_event()?))))
 }
 
+pub(crate) fn poll_pending_request(
+    context: &eis::Context,
+) -> Option<Poll<Option<io::Result<PendingRequestResult<eis::Request>>>>> {
+    Some(Poll::Ready(Some(Ok(context.pending_request()?))))
+}
+
 pub(crate) struct EiConvertEventStream<
     S: Stream<Item = io::Result<PendingRequestResult<ei::Event>>> + Unpin,
 > {
@@ -79,7 +121,16 @@ pub async fn ei_handshake<S>(
 where
     S: Stream<Item = io::Result<PendingRequestResult<ei::Event>>> + Unpin,
 {
-    let mut handshaker = EiHandshaker::new(name, context_type);
+    ei_handshake_with(events, EiHandshaker::new(name, context_type)).await
+}
+
+pub async fn ei_handshake_with<S>(
+    events: &mut S,
+    mut handshaker: EiHandshaker<'_>,
+) -> Result<HandshakeResp, Error>
+where
+    S: Stream<Item = io::Result<PendingRequestResult<ei::Event>>> + Unpin,
+{
     while let Some(result) = events.next().await {
         let request = crate::handshake::request_result(result?)?;
         if let Some(resp) = handshaker.handle_event(request)? {
@@ -92,3 +143,250 @@ where
     )
     .into())
 }
+
+// Timer of the runtime, for keepalive pings.
+pub(crate) trait KeepaliveTimer: Unpin {
+    fn new(after: Duration) -> Self;
+    fn reset(&mut self, after: Duration);
+    fn poll_expired(&mut self, context: &mut Context<'_>) -> Poll<()>;
+}
+
+pub(crate) struct Keepalive<T> {
+    timer: T,
+    interval: Duration,
+    timeout: Duration,
+    ping: Option<request::Ping>,
+}
+
+impl<T: KeepaliveTimer> Keepalive<T> {
+    pub(crate) fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            timer: T::new(interval),
+            interval,
+            timeout,
+            ping: None,
+        }
+    }
+
+    // Returns `true` if the client didn't answer the last ping in time.
+    fn poll(&mut self, handle: &request::Connection, context: &mut Context<'_>) -> bool {
+        while self.timer.poll_expired(context).is_ready() {
+            match self.ping.take() {
+                None => {
+                    self.ping = Some(handle.ping());
+                    let _ = handle.flush();
+                    self.timer.reset(self.timeout);
+                }
+                Some(ping) if ping.is_done() => self.timer.reset(self.interval),
+                Some(_) => return true,
+            }
+        }
+        false
+    }
+}
+
+pub(crate) struct EisConvertRequestStream<
+    S: Stream<Item = io::Result<PendingRequestResult<eis::Request>>> + Unpin,
+    T,
+> {
+    inner: S,
+    pub(crate) converter: EisRequestConverter,
+    pub(crate) keepalive: Option<Keepalive<T>>,
+    done: bool,
+}
+
+impl<S: Stream<Item = io::Result<PendingRequestResult<eis::Request>>> + Unpin, T>
+    EisConvertRequestStream<S, T>
+{
+    pub(crate) fn new(
+        inner: S,
+        context: &eis::Context,
+        resp: EisHandshakeResp,
+    ) -> Result<Self, Error> {
+        let converter = EisRequestConverter::new(context, resp, 1);
+        converter.handle().check_required_interfaces()?;
+        Ok(Self {
+            inner,
+            converter,
+            keepalive: None,
+            done: false,
+        })
+    }
+
+    fn request(&mut self, request: EisRequest) -> Poll<Option<Result<EisRequest, Error>>> {
+        if matches!(request, EisRequest::Disconnect) {
+            self.done = true;
+        }
+        Poll::Ready(Some(Ok(request)))
+    }
+
+    // Sends the error to the client, and ends the stream after returning it
+    fn error(&mut self, err: Error) -> Poll<Option<Result<EisRequest, Error>>> {
+        self.converter.handle().disconnect_with_error(&err);
+        self.done = true;
+        Poll::Ready(Some(Err(err)))
+    }
+}
+
+impl<S, T> Stream for EisConvertRequestStream<S, T>
+where
+    S: Stream<Item = io::Result<PendingRequestResult<eis::Request>>> + Unpin,
+    T: KeepaliveTimer,
+{
+    type Item = Result<EisRequest, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Some(request) = self.converter.next_request() {
+            return self.request(request);
+        }
+        let this = &mut *self;
+        if let Some(keepalive) = &mut this.keepalive {
+            if keepalive.poll(this.converter.handle(), context) {
+                this.keepalive = None;
+                this.converter.handle().disconnected(
+                    eis::connection::DisconnectReason::Error,
+                    Some("Client did not respond to ping"),
+                );
+            }
+        }
+        loop {
+            // If server has sent `disconected`, return `Disconnect` and end the stream.
+            if self.converter.handle().has_sent_disconnected() {
+                return self.request(EisRequest::Disconnect);
+            }
+            match ready!(self.inner.poll_next_unpin(context)) {
+                Some(Ok(res)) => match res {
+                    PendingRequestResult::Request(request) => {
+                        if let Err(err) = self.converter.handle_request(request) {
+                            return self.error(err);
+                        }
+                        if let Some(request) = self.converter.next_request() {
+                            return self.request(request);
+                        }
+                    }
+                    PendingRequestResult::ParseError(err) => {
+                        return self.error(err.into());
+                    }
+                    PendingRequestResult::InvalidObject(object_id) => {
+                        log::debug!("reis: Failed to find object {object_id}");
+                        let handle = self.converter.handle();
+                        handle
+                            .connection()
+                            .invalid_object(handle.last_serial(), object_id);
+                    }
+                },
+                Some(Err(err)) => {
+                    return self.error(err.into());
+                }
+                None => {
+                    self.done = true;
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+}
+
+/// Handle for resolving an [`Authorization::Defer`] decision of the authorizer of an
+/// [`EisHandshaker`], during an async handshake.
+///
+/// Create one before the handshake, and resolve a clone of it from the authorizer or later.
+#[derive(Clone, Debug, Default)]
+pub struct AuthorizationHandle(Arc<Mutex<DeferredAuthorization>>);
+
+#[derive(Debug, Default)]
+struct DeferredAuthorization {
+    decision: Option<Authorization>,
+    waker: Option<Waker>,
+}
+
+impl AuthorizationHandle {
+    /// Creates a handle with no decision.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Completes the handshake with the given decision, from any thread.
+    ///
+    /// Resolving with [`Authorization::Defer`] keeps the client waiting.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    pub fn resolve(&self, authorization: Authorization) {
+        let mut deferred = self.0.lock().unwrap();
+        deferred.decision = Some(authorization);
+        if let Some(waker) = deferred.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_decision(&self, context: &mut Context<'_>) -> Poll<Authorization> {
+        let mut deferred = self.0.lock().unwrap();
+        if let Some(decision) = deferred.decision.take() {
+            Poll::Ready(decision)
+        } else {
+            deferred.waker = Some(context.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+// Runs the handshake. A deferred authorization is waited for if there is a handle to resolve
+// it, and fails the handshake otherwise.
+pub async fn eis_handshake_with<S>(
+    requests: &mut S,
+    context: &eis::Context,
+    mut handshaker: EisHandshaker,
+    authorization: Option<&AuthorizationHandle>,
+) -> Result<EisHandshakeResp, Error>
+where
+    S: Stream<Item = io::Result<PendingRequestResult<eis::Request>>> + Unpin,
+{
+    loop {
+        let decision = match (handshaker.is_deferred(), authorization) {
+            (false, _) => None,
+            (true, None) => {
+                let _ = handshaker.authorize(Authorization::Reject {
+                    reason: eis::connection::DisconnectReason::Error,
+                    explanation: Some("Authorization can't be deferred".to_owned()),
+                });
+                return Err(HandshakeError::Deferred.into());
+            }
+            (true, Some(authorization)) => Some(authorization),
+        };
+        let next = poll_fn(|cx| {
+            if let Some(Poll::Ready(decision)) = decision.map(|handle| handle.poll_decision(cx)) {
+                return Poll::Ready(Err(decision));
+            }
+            requests.poll_next_unpin(cx).map(Ok)
+        })
+        .await;
+        let resp = match next {
+            Ok(Some(result)) => {
+                let request = crate::handshake::request_result(result?)?;
+                handshaker.handle_request(request)?
+            }
+            Ok(None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "unexpected EOF reading ei socket",
+                )
+                .into())
+            }
+            Err(decision) => handshaker.authorize(decision)?,
+        };
+        if let Some(resp) = resp {
+            // XXX
+            let _ = context.flush();
+            return Ok(resp);
+        }
+    }
+}
@@ -1,18 +1,45 @@
 //! Module containing [`calloop`] sources.
 
-// TODO: Produce an event of some kind on disconnect/eof?
-
-use calloop::{generic::Generic, Interest, Mode, PostAction, Readiness, Token, TokenFactory};
-use std::io;
+use calloop::{
+    generic::Generic,
+    ping::{Ping, PingSource},
+    EventSource, Interest, Mode, PostAction, Readiness, Token, TokenFactory,
+};
+use rustix::{
+    io::Errno,
+    time::{Itimerspec, TimerfdClockId, TimerfdFlags, TimerfdTimerFlags, Timespec},
+};
+use std::{
+    fmt, io,
+    os::unix::io::OwnedFd,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use crate::{
-    eis,
-    handshake::HandshakeError,
-    request::{self, Connection, EisRequestConverter, RequestError},
+    ei, eis,
+    event::{self, EiEventConverter},
+    handshake::{
+        Authorization, ClientInfo, EiHandshaker, EisHandshakeResp, HandshakeError,
+        InterfaceVersions,
+    },
+    request::{self, Connection, EisRequestConverter},
+    wire::{Backend, WriteWaker},
     Error, PendingRequestResult,
 };
 
+// The sources may be created on another thread than the one running the event loop
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<EisListenerSource>();
+    assert_send::<EisRequestSource>();
+    assert_send::<EiEventSource>();
+};
+
 /// [`calloop`] source that receives EI connections by listening on a socket.
+///
+/// The credentials of the connecting process are passed to the callback, as returned by
+/// [`eis::Context::peer_credentials`].
 #[derive(Debug)]
 pub struct EisListenerSource {
     source: Generic<eis::Listener>,
@@ -30,7 +57,7 @@ impl EisListenerSource {
 
 impl calloop::EventSource for EisListenerSource {
     type Event = eis::Context;
-    type Metadata = ();
+    type Metadata = Option<eis::PeerCredentials>;
     type Ret = io::Result<PostAction>;
     type Error = io::Error;
 
@@ -41,12 +68,13 @@ impl calloop::EventSource for EisListenerSource {
         mut cb: F,
     ) -> io::Result<PostAction>
     where
-        F: FnMut(eis::Context, &mut ()) -> io::Result<PostAction>,
+        F: FnMut(eis::Context, &mut Option<eis::PeerCredentials>) -> io::Result<PostAction>,
     {
         self.source
             .process_events(readiness, token, |_readiness, listener| {
                 if let Some(context) = listener.accept()? {
-                    cb(context, &mut ())
+                    let mut peer_credentials = context.peer_credentials().copied();
+                    cb(context, &mut peer_credentials)
                 } else {
                     Ok(PostAction::Continue)
                 }
@@ -86,30 +114,20 @@ impl ConnectedContextState {
     where
         F: FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
     {
-        // If server has sent `disconected`, return `Disconnect` event and stop polling.
-        if self.handle.has_sent_disconnected() {
-            // TODO express if server or client requested disconnect?
-            handle_result(
-                Ok(EisRequestSourceEvent::Request(
-                    request::EisRequest::Disconnect,
-                )),
-                &mut self.handle,
-                &mut cb,
-            )?;
-            return Ok(calloop::PostAction::Remove);
+        // If server has sent `disconected`, return `Disconnected` event and stop polling.
+        if let Some(reason) = self.handle.sent_disconnect_reason() {
+            return self.disconnected(DisconnectInitiator::Server, reason, None, &mut cb);
         }
 
         if let Err(err) = self.context.read() {
-            handle_result(Err(Error::Io(err)), &mut self.handle, &mut cb)?;
-            return Ok(calloop::PostAction::Remove);
+            return self.error(Error::Io(err), &mut cb);
         }
 
         while let Some(result) = self.context.pending_request() {
             let request = match result {
                 PendingRequestResult::Request(request) => request,
                 PendingRequestResult::ParseError(err) => {
-                    handle_result(Err(Error::Parse(err)), &mut self.handle, &mut cb)?;
-                    return Ok(calloop::PostAction::Remove);
+                    return self.error(Error::Parse(err), &mut cb);
                 }
                 PendingRequestResult::InvalidObject(object_id) => {
                     log::debug!("reis: Failed to find object {object_id}");
@@ -122,18 +140,23 @@ impl ConnectedContextState {
             };
 
             if let Err(err) = self.request_converter.handle_request(request) {
-                handle_result(Err(err), &mut self.handle, &mut cb)?;
-                return Ok(calloop::PostAction::Remove);
+                return self.error(err, &mut cb);
             }
             while let Some(request) = self.request_converter.next_request() {
-                let disconnected = matches!(request, request::EisRequest::Disconnect);
-                let res = handle_result(
+                if let request::EisRequest::Disconnect = request {
+                    return self.disconnected(
+                        DisconnectInitiator::Client,
+                        eis::connection::DisconnectReason::Disconnected,
+                        None,
+                        &mut cb,
+                    );
+                }
+                let res = cb(
                     Ok(EisRequestSourceEvent::Request(request)),
                     &mut self.handle,
-                    &mut cb,
                 )?;
-                if disconnected {
-                    return Ok(calloop::PostAction::Remove);
+                if let Some(reason) = self.handle.sent_disconnect_reason() {
+                    return self.disconnected(DisconnectInitiator::Server, reason, None, &mut cb);
                 }
                 if res != calloop::PostAction::Continue {
                     return Ok(res);
@@ -143,31 +166,45 @@ impl ConnectedContextState {
 
         Ok(calloop::PostAction::Continue)
     }
-}
 
-fn handle_result(
-    res: Result<EisRequestSourceEvent, Error>,
-    connection: &mut Connection,
-    cb: &mut impl FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
-) -> io::Result<PostAction> {
-    // Send error to client
-    if let Err(err) = &res {
-        let reason = if let Error::Request(RequestError::InvalidCapabilities) = err {
-            eis::connection::DisconnectReason::Value
+    // Sends a protocol error to the client, unless the socket failed, and reports the
+    // disconnection.
+    fn error<F>(&mut self, err: Error, cb: &mut F) -> io::Result<PostAction>
+    where
+        F: FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
+    {
+        let (initiator, reason) = if let Error::Io(_) = err {
+            (
+                DisconnectInitiator::Client,
+                eis::connection::DisconnectReason::Transport,
+            )
         } else {
-            eis::connection::DisconnectReason::Protocol
+            (
+                DisconnectInitiator::Server,
+                self.handle.disconnect_with_error(&err),
+            )
         };
-        connection.disconnected(reason, Some(&err.to_string()));
-        let _ = connection.flush();
+        self.disconnected(initiator, reason, Some(err), cb)
     }
 
-    let is_err = res.is_err();
-    let action = cb(res, connection)?;
-    Ok(if is_err {
-        calloop::PostAction::Remove
-    } else {
-        action
-    })
+    fn disconnected<F>(
+        &mut self,
+        initiator: DisconnectInitiator,
+        reason: eis::connection::DisconnectReason,
+        error: Option<Error>,
+        cb: &mut F,
+    ) -> io::Result<PostAction>
+    where
+        F: FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
+    {
+        let event = EisRequestSourceEvent::Disconnected {
+            initiator,
+            reason,
+            error,
+        };
+        cb(Ok(event), &mut self.handle)?;
+        Ok(calloop::PostAction::Remove)
+    }
 }
 
 fn process_handshake(
@@ -179,25 +216,7 @@ fn process_handshake(
     while let Some(result) = context.pending_request() {
         let request = crate::handshake::request_result(result)?;
         if let Some(resp) = handshaker.handle_request(request)? {
-            let request_converter = EisRequestConverter::new(context, resp, 1);
-            let handle = request_converter.handle().clone();
-
-            if !handle.has_interface("ei_seat") || !handle.has_interface("ei_device") {
-                handle.disconnected(
-                    eis::connection::DisconnectReason::Protocol,
-                    Some("Need `ei_seat` and `ei_device`"),
-                );
-                let _ = request_converter.handle().flush();
-                return Err(HandshakeError::MissingInterface.into());
-            }
-
-            let connected_state = ConnectedContextState {
-                context: context.clone(),
-                handle,
-                request_converter,
-            };
-
-            return Ok(Some(connected_state));
+            return connected_state(context, resp).map(Some);
         }
     }
 
@@ -207,6 +226,90 @@ fn process_handshake(
     Ok(None)
 }
 
+fn connected_state(
+    context: &eis::Context,
+    resp: EisHandshakeResp,
+) -> Result<ConnectedContextState, Error> {
+    let request_converter = EisRequestConverter::new(context, resp, 1);
+    let handle = request_converter.handle().clone();
+    handle.check_required_interfaces()?;
+
+    Ok(ConnectedContextState {
+        context: context.clone(),
+        handle,
+        request_converter,
+    })
+}
+
+// Moves to the connected state once the handshake has completed.
+fn finish_handshake<F>(
+    state: &mut State,
+    res: Result<Option<ConnectedContextState>, Error>,
+    on_error: &mut Option<HandshakeErrorHandler>,
+    cb: &mut F,
+) -> io::Result<PostAction>
+where
+    F: FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
+{
+    match res {
+        Ok(Some(mut connected)) => {
+            let peer_credentials = connected.context.peer_credentials().copied();
+            let res = cb(
+                Ok(EisRequestSourceEvent::Connected { peer_credentials }),
+                &mut connected.handle,
+            )?;
+            *state = State::Connected(connected);
+            Ok(res)
+        }
+        Ok(None) => Ok(calloop::PostAction::Continue),
+        Err(err) => {
+            if let Some(on_error) = on_error {
+                (on_error.0)(err);
+            } else {
+                log::warn!("reis: Client handshake failed: {err}");
+            }
+            Ok(calloop::PostAction::Remove)
+        }
+    }
+}
+
+struct HandshakeErrorHandler(Box<dyn FnMut(Error) + Send>);
+
+impl fmt::Debug for HandshakeErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HandshakeErrorHandler")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle for resolving an [`Authorization::Defer`] decision of the authorizer passed to
+/// [`EisRequestSource::with_authorizer`].
+#[derive(Clone, Debug)]
+pub struct AuthorizationHandle {
+    decision: Arc<Mutex<Option<Authorization>>>,
+    ping: Ping,
+}
+
+impl AuthorizationHandle {
+    /// Completes the handshake with the given decision, from any thread.
+    ///
+    /// Resolving with [`Authorization::Defer`] keeps the client waiting.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    pub fn resolve(&self, authorization: Authorization) {
+        *self.decision.lock().unwrap() = Some(authorization);
+        self.ping.ping();
+    }
+}
+
+#[derive(Debug)]
+struct DeferredAuthorization {
+    source: PingSource,
+    handle: AuthorizationHandle,
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 enum State {
@@ -214,14 +317,154 @@ enum State {
     Connected(ConnectedContextState),
 }
 
+// One-shot timer on a timerfd. Unlike `calloop::timer::Timer`, this is `Send`, so the sources
+// can be created on another thread than the event loop.
+//
+// The timerfd is created and armed when the source is registered.
+#[derive(Debug)]
+struct TimerSource {
+    duration: Duration,
+    source: Option<Generic<OwnedFd>>,
+}
+
+impl TimerSource {
+    fn from_duration(duration: Duration) -> Self {
+        Self {
+            duration,
+            source: None,
+        }
+    }
+
+    // Expires after `duration`, replacing any previous deadline.
+    fn set(&mut self, duration: Duration) -> io::Result<()> {
+        self.duration = duration;
+        let Some(source) = &self.source else {
+            return Ok(());
+        };
+        // A zero value would disarm the timer
+        let duration = duration.max(Duration::from_nanos(1));
+        let value = Itimerspec {
+            it_interval: Timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: Timespec {
+                tv_sec: duration.as_secs().try_into().unwrap_or(i64::MAX),
+                tv_nsec: duration.subsec_nanos().into(),
+            },
+        };
+        rustix::time::timerfd_settime(source.get_ref(), TimerfdTimerFlags::empty(), &value)?;
+        Ok(())
+    }
+
+    // Returns `true` if the timer has expired.
+    fn process(&mut self, readiness: Readiness, token: Token) -> io::Result<bool> {
+        let Some(source) = &mut self.source else {
+            return Ok(false);
+        };
+        let mut expired = false;
+        source.process_events(readiness, token, |_readiness, fd| {
+            let mut expirations = [0; 8];
+            match rustix::io::read(&**fd, &mut expirations) {
+                Ok(_) => expired = true,
+                #[allow(unreachable_patterns)] // `WOULDBLOCK` and `AGAIN` typically equal
+                Err(Errno::WOULDBLOCK | Errno::AGAIN) => {}
+                Err(err) => return Err(err.into()),
+            }
+            Ok(PostAction::Continue)
+        })?;
+        Ok(expired)
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        let fd = rustix::time::timerfd_create(
+            TimerfdClockId::Monotonic,
+            TimerfdFlags::CLOEXEC | TimerfdFlags::NONBLOCK,
+        )
+        .map_err(io::Error::from)?;
+        let source = self
+            .source
+            .insert(Generic::new(fd, Interest::READ, Mode::Level));
+        source.register(poll, token_factory)?;
+        self.set(self.duration)?;
+        Ok(())
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        if let Some(source) = &mut self.source {
+            source.reregister(poll, token_factory)?;
+        }
+        Ok(())
+    }
+
+    fn unregister(&mut self, poll: &mut calloop::Poll) -> Result<(), calloop::Error> {
+        if let Some(source) = &mut self.source {
+            source.unregister(poll)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Keepalive {
+    timer: TimerSource,
+    interval: Duration,
+    timeout: Duration,
+    ping: Option<request::Ping>,
+}
+
+impl Keepalive {
+    // Returns `true` if the client didn't answer the last ping in time.
+    fn process(&mut self, readiness: Readiness, token: Token, state: &State) -> io::Result<bool> {
+        if !self.timer.process(readiness, token)? {
+            return Ok(false);
+        }
+        let State::Connected(state) = state else {
+            self.timer.set(self.interval)?;
+            return Ok(false);
+        };
+        match self.ping.take() {
+            None => {
+                self.ping = Some(state.handle.ping());
+                let _ = state.handle.flush();
+                self.timer.set(self.timeout)?;
+            }
+            Some(ping) if ping.is_done() => self.timer.set(self.interval)?,
+            Some(_) => return Ok(true),
+        }
+        Ok(false)
+    }
+}
+
 /// [`calloop`] source that receives EI protocol requests.
 ///
-/// If an error occurs, the error is sent to the callback after sending a protocol
-/// error to the client.
+/// When the connection ends, [`EisRequestSourceEvent::Disconnected`] is sent to the callback
+/// and the source is removed. If this is because of a protocol error of the client, a
+/// protocol error is sent to the client first.
+///
+/// If a [flush](Connection::flush) leaves messages buffered because the socket is full, the
+/// source sends them once the socket becomes writable.
+///
+/// The credentials of the client process are passed with
+/// [`EisRequestSourceEvent::Connected`], and are also available from the [`Connection`] given
+/// to the callback, with [`Connection::peer_credentials`].
 #[derive(Debug)]
 pub struct EisRequestSource {
     source: Generic<eis::Context>,
     state: State,
+    keepalive: Option<Keepalive>,
+    authorization: Option<DeferredAuthorization>,
+    handshake_timer: Option<TimerSource>,
+    handshake_error_handler: Option<HandshakeErrorHandler>,
+    write_wakeup: WriteWakeup,
 }
 
 impl EisRequestSource {
@@ -232,7 +475,182 @@ impl EisRequestSource {
         Self {
             source: Generic::new(context, Interest::READ, Mode::Level),
             state: State::Handshake(handshaker),
+            keepalive: None,
+            authorization: None,
+            handshake_timer: None,
+            handshake_error_handler: None,
+            write_wakeup: WriteWakeup::default(),
+        }
+    }
+
+    /// Calls `handler` if the handshake fails, before the source is removed.
+    ///
+    /// The callback of the source can't receive these errors, since there is no
+    /// [`Connection`] yet. Without a handler, they are logged with [`log::warn`].
+    #[must_use]
+    pub fn with_handshake_error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(Error) + Send + 'static,
+    {
+        self.handshake_error_handler = Some(HandshakeErrorHandler(Box::new(handler)));
+        self
+    }
+
+    /// Disconnects the client if it hasn't finished the handshake within `timeout`.
+    ///
+    /// The timeout is reported like other handshake errors, with [`HandshakeError::Timeout`].
+    /// See [`EisRequestSource::with_handshake_error_handler`].
+    #[must_use]
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        if let State::Handshake(handshaker) = &mut self.state {
+            handshaker.set_timeout(timeout);
+            self.handshake_timer = Some(TimerSource::from_duration(timeout));
+        }
+        self
+    }
+
+    /// Sets the interfaces and maximum versions supported by the server.
+    ///
+    /// See [`EisHandshaker::set_interfaces`](crate::handshake::EisHandshaker::set_interfaces).
+    #[must_use]
+    pub fn with_interfaces(mut self, interfaces: InterfaceVersions) -> Self {
+        if let State::Handshake(handshaker) = &mut self.state {
+            handshaker.set_interfaces(interfaces);
         }
+        self
+    }
+
+    /// Sets a policy deciding whether a client may connect.
+    ///
+    /// See [`EisHandshaker::set_authorizer`](crate::handshake::EisHandshaker::set_authorizer).
+    /// To ask the user before deciding, return [`Authorization::Defer`] and later call
+    /// [`AuthorizationHandle::resolve`] on a clone of the handle.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the wakeup source for deferred decisions can't be created.
+    pub fn with_authorizer<F>(mut self, mut authorizer: F) -> io::Result<Self>
+    where
+        F: FnMut(&ClientInfo<'_>, &AuthorizationHandle) -> Authorization + Send + 'static,
+    {
+        let (ping, source) = calloop::ping::make_ping()?;
+        let handle = AuthorizationHandle {
+            decision: Arc::new(Mutex::new(None)),
+            ping,
+        };
+        if let State::Handshake(handshaker) = &mut self.state {
+            let handle = handle.clone();
+            handshaker.set_authorizer(move |info| authorizer(info, &handle));
+        }
+        self.authorization = Some(DeferredAuthorization { source, handle });
+        Ok(self)
+    }
+
+    /// Periodically checks that the client is still responsive.
+    ///
+    /// Once connected, the client is pinged with [`Connection::ping`]. If it doesn't answer
+    /// within `timeout`, it is disconnected with [`DisconnectReason::Error`]. After an answer,
+    /// the next ping is sent after `interval`.
+    ///
+    /// [`DisconnectReason::Error`]: eis::connection::DisconnectReason::Error
+    #[must_use]
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some(Keepalive {
+            timer: TimerSource::from_duration(interval),
+            interval,
+            timeout,
+            ping: None,
+        });
+        self
+    }
+}
+
+impl EisRequestSource {
+    fn dispatch<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut cb: F,
+    ) -> io::Result<PostAction>
+    where
+        F: FnMut(Result<EisRequestSourceEvent, Error>, &mut Connection) -> io::Result<PostAction>,
+    {
+        let action = self
+            .source
+            .process_events(readiness, token, |_readiness, context| {
+                match &mut self.state {
+                    State::Handshake(handshaker) => {
+                        let res = process_handshake(handshaker, context);
+                        finish_handshake(
+                            &mut self.state,
+                            res,
+                            &mut self.handshake_error_handler,
+                            &mut cb,
+                        )
+                    }
+                    State::Connected(state) => state.process(&mut cb),
+                }
+            })?;
+        if action != PostAction::Continue {
+            return Ok(action);
+        }
+
+        if let Some(authorization) = &mut self.authorization {
+            let mut resolved = false;
+            authorization
+                .source
+                .process_events(readiness, token, |(), ()| resolved = true)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            let decision = if resolved {
+                authorization.handle.decision.lock().unwrap().take()
+            } else {
+                None
+            };
+            if let (Some(decision), State::Handshake(handshaker)) = (decision, &mut self.state) {
+                let context = self.source.get_ref();
+                let res = handshaker
+                    .authorize(decision)
+                    .map_err(Error::from)
+                    .and_then(|resp| resp.map(|resp| connected_state(context, resp)).transpose());
+                let _ = context.flush();
+                let action = finish_handshake(
+                    &mut self.state,
+                    res,
+                    &mut self.handshake_error_handler,
+                    &mut cb,
+                )?;
+                if action != PostAction::Continue {
+                    return Ok(action);
+                }
+            }
+        }
+
+        if let Some(timer) = &mut self.handshake_timer {
+            let timed_out = timer.process(readiness, token)?;
+            if timed_out && matches!(self.state, State::Handshake(_)) {
+                let res = Err(HandshakeError::Timeout.into());
+                return finish_handshake(
+                    &mut self.state,
+                    res,
+                    &mut self.handshake_error_handler,
+                    &mut cb,
+                );
+            }
+        }
+
+        if let Some(keepalive) = &mut self.keepalive {
+            if keepalive.process(readiness, token, &self.state)? {
+                if let State::Connected(state) = &mut self.state {
+                    state.handle.disconnected(
+                        eis::connection::DisconnectReason::Error,
+                        Some("Client did not respond to ping"),
+                    );
+                    return state.process(&mut cb);
+                }
+            }
+        }
+
+        Ok(action)
     }
 }
 
@@ -251,34 +669,33 @@ impl calloop::EventSource for EisRequestSource {
     where
         F: FnMut(Self::Event, &mut Connection) -> io::Result<PostAction>,
     {
-        self.source
-            .process_events(readiness, token, |_readiness, context| {
-                match &mut self.state {
-                    State::Handshake(handshaker) => {
-                        if let Some(res) = process_handshake(handshaker, context).transpose() {
-                            match res {
-                                Ok(mut state) => {
-                                    let res = handle_result(
-                                        Ok(EisRequestSourceEvent::Connected),
-                                        &mut state.handle,
-                                        &mut cb,
-                                    )?;
-                                    self.state = State::Connected(state);
-                                    Ok(res)
-                                }
-                                Err(err) => {
-                                    // TODO return handshake errors?
-                                    eprintln!("Client handshake failed: {err}");
-                                    Ok(calloop::PostAction::Remove)
-                                }
-                            }
-                        } else {
-                            Ok(calloop::PostAction::Continue)
-                        }
-                    }
-                    State::Connected(state) => state.process(&mut cb),
-                }
-            })
+        self.write_wakeup.process(readiness, token)?;
+        let action = self.dispatch(readiness, token, &mut cb)?;
+        if matches!(action, PostAction::Remove | PostAction::Disable) {
+            return Ok(action);
+        }
+
+        let context = self.source.get_ref();
+        if context.has_pending_writes() {
+            if let Err(err) = context.flush() {
+                let err = Error::Io(err.into());
+                return match &mut self.state {
+                    State::Handshake(_) => finish_handshake(
+                        &mut self.state,
+                        Err(err),
+                        &mut self.handshake_error_handler,
+                        &mut cb,
+                    ),
+                    State::Connected(state) => state.error(err, &mut cb),
+                };
+            }
+        }
+        let pending_writes = context.has_pending_writes();
+        Ok(update_write_interest(
+            &mut self.source,
+            pending_writes,
+            action,
+        ))
     }
 
     fn register(
@@ -286,7 +703,18 @@ impl calloop::EventSource for EisRequestSource {
         poll: &mut calloop::Poll,
         token_factory: &mut TokenFactory,
     ) -> Result<(), calloop::Error> {
-        self.source.register(poll, token_factory)
+        self.source.register(poll, token_factory)?;
+        if let Some(keepalive) = &mut self.keepalive {
+            keepalive.timer.register(poll, token_factory)?;
+        }
+        if let Some(authorization) = &mut self.authorization {
+            authorization.source.register(poll, token_factory)?;
+        }
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.register(poll, token_factory)?;
+        }
+        self.write_wakeup
+            .register(&self.source.get_ref().0, poll, token_factory)
     }
 
     fn reregister(
@@ -294,11 +722,31 @@ impl calloop::EventSource for EisRequestSource {
         poll: &mut calloop::Poll,
         token_factory: &mut TokenFactory,
     ) -> Result<(), calloop::Error> {
-        self.source.reregister(poll, token_factory)
+        self.source.reregister(poll, token_factory)?;
+        if let Some(keepalive) = &mut self.keepalive {
+            keepalive.timer.reregister(poll, token_factory)?;
+        }
+        if let Some(authorization) = &mut self.authorization {
+            authorization.source.reregister(poll, token_factory)?;
+        }
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.reregister(poll, token_factory)?;
+        }
+        self.write_wakeup.reregister(poll, token_factory)
     }
 
     fn unregister(&mut self, poll: &mut calloop::Poll) -> Result<(), calloop::Error> {
-        self.source.unregister(poll)
+        self.source.unregister(poll)?;
+        if let Some(keepalive) = &mut self.keepalive {
+            keepalive.timer.unregister(poll)?;
+        }
+        if let Some(authorization) = &mut self.authorization {
+            authorization.source.unregister(poll)?;
+        }
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.unregister(poll)?;
+        }
+        self.write_wakeup.unregister(poll)
     }
 }
 
@@ -307,7 +755,295 @@ impl calloop::EventSource for EisRequestSource {
 #[derive(Debug)]
 pub enum EisRequestSourceEvent {
     /// Handshake has finished.
-    Connected,
+    Connected {
+        /// Credentials of the client process, as returned by
+        /// [`eis::Context::peer_credentials`].
+        peer_credentials: Option<eis::PeerCredentials>,
+    },
     /// High-level request to EIS.
+    ///
+    /// [`EisRequest::Disconnect`](request::EisRequest::Disconnect) is reported as
+    /// [`EisRequestSourceEvent::Disconnected`] instead.
     Request(request::EisRequest),
+    /// Connection has ended, and the source will be removed.
+    ///
+    /// This is the last event of the source.
+    Disconnected {
+        /// Side that ended the connection.
+        initiator: DisconnectInitiator,
+        /// Reason sent to the client by the server, or
+        /// [`Transport`](eis::connection::DisconnectReason::Transport) if the socket was closed
+        /// or failed.
+        reason: eis::connection::DisconnectReason,
+        /// Error that caused the disconnection, if any.
+        error: Option<Error>,
+    },
+}
+
+/// Side that ended a connection, in [`EisRequestSourceEvent::Disconnected`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectInitiator {
+    /// Client sent `ei_connection.disconnect`, closed the socket, or the socket failed.
+    Client,
+    /// Server sent `ei_connection.disconnected`, possibly because of a protocol error of the
+    /// client.
+    Server,
+}
+
+enum EiState {
+    Handshake(EiHandshaker<'static>),
+    Connected(EiEventConverter),
+}
+
+impl EiState {
+    fn process<F>(&mut self, context: &ei::Context, cb: &mut F) -> io::Result<PostAction>
+    where
+        F: FnMut(Result<EiEventSourceEvent, Error>, &mut ()) -> io::Result<PostAction>,
+    {
+        if let Err(err) = context.read() {
+            cb(Err(Error::Io(err)), &mut ())?;
+            return Ok(PostAction::Remove);
+        }
+
+        while let Some(result) = context.pending_event() {
+            let event = match result {
+                PendingRequestResult::Request(event) => event,
+                PendingRequestResult::ParseError(err) => {
+                    cb(Err(Error::Parse(err)), &mut ())?;
+                    return Ok(PostAction::Remove);
+                }
+                PendingRequestResult::InvalidObject(object_id) => {
+                    log::debug!("reis: Failed to find object {object_id}");
+                    continue;
+                }
+            };
+
+            match self {
+                Self::Handshake(handshaker) => match handshaker.handle_event(event) {
+                    Ok(Some(resp)) => {
+                        let converter = EiEventConverter::new(context, resp);
+                        let connection = converter.connection().clone();
+                        *self = Self::Connected(converter);
+                        let action = cb(Ok(EiEventSourceEvent::Connected(connection)), &mut ())?;
+                        if action != PostAction::Continue {
+                            return Ok(action);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        cb(Err(err.into()), &mut ())?;
+                        return Ok(PostAction::Remove);
+                    }
+                },
+                Self::Connected(converter) => {
+                    if let Err(err) = converter.handle_event(event) {
+                        cb(Err(err.into()), &mut ())?;
+                        return Ok(PostAction::Remove);
+                    }
+                    while let Some(event) = converter.next_event() {
+                        let disconnected = matches!(event, event::EiEvent::Disconnected(_));
+                        let action = cb(Ok(EiEventSourceEvent::Event(event)), &mut ())?;
+                        if disconnected {
+                            return Ok(PostAction::Remove);
+                        }
+                        if action != PostAction::Continue {
+                            return Ok(action);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(PostAction::Continue)
+    }
+}
+
+/// [`calloop`] source that performs the EI handshake and receives converted events from the
+/// server.
+///
+/// If an error occurs, including EOF on the socket, the error is sent to the callback and the
+/// source is removed. It is also removed after [`EiEvent::Disconnected`](event::EiEvent).
+/// Buffered requests are flushed after each dispatch. If the socket is full, the source also
+/// waits for it to become writable, until the remaining requests are sent.
+pub struct EiEventSource {
+    source: Generic<ei::Context>,
+    state: EiState,
+    handshake_timer: Option<TimerSource>,
+    write_wakeup: WriteWakeup,
+}
+
+impl fmt::Debug for EiEventSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EiEventSource")
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EiEventSource {
+    /// Creates a new EI event source, which performs the handshake with `handshaker`.
+    ///
+    /// The [timeout](EiHandshaker::with_timeout) of the handshaker is enforced with a timer.
+    #[must_use]
+    pub fn new(context: ei::Context, handshaker: EiHandshaker<'static>) -> Self {
+        Self {
+            source: Generic::new(context, Interest::READ, Mode::Level),
+            handshake_timer: handshaker.timeout().map(TimerSource::from_duration),
+            state: EiState::Handshake(handshaker),
+            write_wakeup: WriteWakeup::default(),
+        }
+    }
+}
+
+impl calloop::EventSource for EiEventSource {
+    type Event = Result<EiEventSourceEvent, Error>;
+    type Metadata = ();
+    type Ret = io::Result<PostAction>;
+    type Error = io::Error;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut cb: F,
+    ) -> io::Result<PostAction>
+    where
+        F: FnMut(Self::Event, &mut ()) -> io::Result<PostAction>,
+    {
+        self.write_wakeup.process(readiness, token)?;
+        let action = self
+            .source
+            .process_events(readiness, token, |_readiness, context| {
+                self.state.process(context, &mut cb)
+            })?;
+        if action != PostAction::Continue {
+            return Ok(action);
+        }
+
+        if let Some(timer) = &mut self.handshake_timer {
+            let timed_out = timer.process(readiness, token)?;
+            if timed_out && matches!(self.state, EiState::Handshake(_)) {
+                cb(Err(HandshakeError::Timeout.into()), &mut ())?;
+                return Ok(PostAction::Remove);
+            }
+        }
+
+        let context = self.source.get_ref();
+        if let Err(err) = context.flush() {
+            cb(Err(Error::Io(err.into())), &mut ())?;
+            return Ok(PostAction::Remove);
+        }
+        let pending_writes = context.has_pending_writes();
+        Ok(update_write_interest(
+            &mut self.source,
+            pending_writes,
+            action,
+        ))
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        self.source.register(poll, token_factory)?;
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.register(poll, token_factory)?;
+        }
+        self.write_wakeup
+            .register(&self.source.get_ref().0, poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        self.source.reregister(poll, token_factory)?;
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.reregister(poll, token_factory)?;
+        }
+        self.write_wakeup.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut calloop::Poll) -> Result<(), calloop::Error> {
+        self.source.unregister(poll)?;
+        if let Some(timer) = &mut self.handshake_timer {
+            timer.unregister(poll)?;
+        }
+        self.write_wakeup.unregister(poll)
+    }
+}
+
+// Wakes the source when a flush outside of its dispatch leaves messages buffered, so it starts
+// polling the socket for writability right away.
+#[derive(Debug, Default)]
+struct WriteWakeup(Option<PingSource>);
+
+impl WriteWakeup {
+    fn register(
+        &mut self,
+        backend: &Backend,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        let (ping, source) = calloop::ping::make_ping()?;
+        backend.set_write_waker(Some(WriteWaker::new(move || ping.ping())));
+        self.0.insert(source).register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut calloop::Poll,
+        token_factory: &mut TokenFactory,
+    ) -> Result<(), calloop::Error> {
+        if let Some(source) = &mut self.0 {
+            source.reregister(poll, token_factory)?;
+        }
+        Ok(())
+    }
+
+    fn unregister(&mut self, poll: &mut calloop::Poll) -> Result<(), calloop::Error> {
+        if let Some(source) = &mut self.0 {
+            source.unregister(poll)?;
+        }
+        Ok(())
+    }
+
+    // Buffered messages are flushed by the caller after each dispatch
+    fn process(&mut self, readiness: Readiness, token: Token) -> io::Result<()> {
+        if let Some(source) = &mut self.0 {
+            source
+                .process_events(readiness, token, |(), ()| {})
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        }
+        Ok(())
+    }
+}
+
+// Polls the socket for writability only while messages remain buffered, since it is almost
+// always writable.
+fn update_write_interest<T: std::os::unix::io::AsFd>(
+    source: &mut Generic<T>,
+    pending_writes: bool,
+    action: PostAction,
+) -> PostAction {
+    if source.interest.writable == pending_writes {
+        return action;
+    }
+    source.interest = if pending_writes {
+        Interest::BOTH
+    } else {
+        Interest::READ
+    };
+    PostAction::Reregister
+}
+
+/// Event returned by [`EiEventSource`].
+#[derive(Debug)]
+pub enum EiEventSourceEvent {
+    /// Handshake has finished.
+    Connected(event::Connection),
+    /// High-level event from EIS.
+    Event(event::EiEvent),
 }
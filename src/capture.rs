@@ -0,0 +1,351 @@
+//! Recording the messages of a connection to a capture file, and decoding them.
+//!
+//! A [`CaptureRecorder`] registered with [`ei::Context::set_capture_recorder`] or
+//! [`eis::Context::set_capture_recorder`] writes one record per message sent or received, as
+//! it is sent or parsed. Tools like `reis-dump` decode the records of a capture.
+//!
+//! # Format
+//!
+//! The capture is in JSON lines format: each line is a JSON object with these fields.
+//!
+//! - `timestamp_us`: Time the message was recorded, in microseconds since the Unix epoch.
+//! - `side`: `"ei"` if the capture was recorded by a client, `"eis"` if it was recorded by an
+//!   EIS implementation.
+//! - `direction`: `"in"` for messages received from the peer, `"out"` for messages sent.
+//! - `object_id`, `length` and `opcode`: Fields of the message header. The length includes
+//!   the 16 byte header.
+//! - `interface`: Interface of the object, like `"ei_device"`, looked up when the message was
+//!   recorded. `null` if there is no such object.
+//! - `payload`: Bytes after the header, as lowercase hexadecimal. Integers are in the native
+//!   byte order of the recording machine.
+//! - `fds`: Number of file descriptors attached to the message. They can't be recorded.
+//!
+//! For example:
+//!
+//! ```text
+//! {"timestamp_us":1700000000000000,"side":"ei","direction":"out","object_id":0,"length":20,"opcode":0,"interface":"ei_handshake","payload":"01000000","fds":0}
+//! ```
+//!
+//! [`ei::Context::set_capture_recorder`]: crate::ei::Context::set_capture_recorder
+//! [`eis::Context::set_capture_recorder`]: crate::eis::Context::set_capture_recorder
+
+use std::{
+    fmt::{self, Write as _},
+    fs,
+    io::{self, Write},
+    os::unix::io::OwnedFd,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    ei, eis,
+    observer::Direction,
+    wire::{self, Arg, Backend, ByteStream, Header, MessageEnum, ParseError},
+    Object, PendingRequestResult,
+};
+
+/// Writes the messages of a context to a capture, in the [format](self#format) of this module.
+pub struct CaptureRecorder {
+    writer: Box<dyn Write + Send>,
+    // Reused for each record
+    line: String,
+    failed: bool,
+}
+
+impl fmt::Debug for CaptureRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CaptureRecorder")
+            .field("failed", &self.failed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CaptureRecorder {
+    /// Creates a recorder writing to `writer`.
+    ///
+    /// Each record is written with a single `write_all` call. Wrap the writer in an
+    /// [`io::LineWriter`] or [`io::BufWriter`] to reduce the number of writes.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: Box::new(writer),
+            line: String::new(),
+            failed: false,
+        }
+    }
+
+    /// Creates a recorder writing to a new file at `path`, replacing any existing file.
+    ///
+    /// Records are flushed to the file as they are written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be created.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(io::LineWriter::new(fs::File::create(path)?)))
+    }
+
+    /// Records a message sent by `sender`, for tools that forward the messages of a connection
+    /// instead of using a context.
+    ///
+    /// `message` is the header and payload, as sent on the wire. It's recorded from the
+    /// perspective of the EIS implementation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `message` is shorter than a message header.
+    #[doc(hidden)]
+    pub fn record_message(
+        &mut self,
+        sender: Side,
+        message: &[u8],
+        interface: Option<&str>,
+        fds: usize,
+    ) {
+        let header = Header::parse(message[..16].try_into().unwrap());
+        let direction = match sender {
+            Side::Client => Direction::Incoming,
+            Side::Server => Direction::Outgoing,
+        };
+        self.record(false, direction, &header, interface, &message[16..], fds);
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        client: bool,
+        direction: Direction,
+        header: &Header,
+        interface: Option<&str>,
+        payload: &[u8],
+        fds: usize,
+    ) {
+        if self.failed {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros());
+        let side = if client { "ei" } else { "eis" };
+        let direction = match direction {
+            Direction::Incoming => "in",
+            Direction::Outgoing => "out",
+        };
+
+        let line = &mut self.line;
+        line.clear();
+        let _ = write!(
+            line,
+            r#"{{"timestamp_us":{timestamp},"side":"{side}","direction":"{direction}","object_id":{},"length":{},"opcode":{},"interface":"#,
+            header.object_id, header.length, header.opcode,
+        );
+        if let Some(interface) = interface {
+            write_json_string(line, interface);
+        } else {
+            line.push_str("null");
+        }
+        line.push_str(r#","payload":""#);
+        for byte in payload {
+            let _ = write!(line, "{byte:02x}");
+        }
+        let _ = writeln!(line, r#"","fds":{fds}}}"#);
+
+        if let Err(err) = self.writer.write_all(line.as_bytes()) {
+            log::warn!("reis: Failed to write capture, stopping recording: {err}");
+            self.failed = true;
+        }
+    }
+}
+
+fn write_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// The decoder and the types used with it are only meant for the `reis-dump` and `reis-proxy`
+// tools, so they are hidden and may change without a semver bump.
+
+/// Side of a connection.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// The EI client, which sends requests.
+    Client,
+    /// The EIS implementation, which sends events.
+    Server,
+}
+
+/// Message decoded by a [`Decoder`].
+#[doc(hidden)]
+#[derive(Debug)]
+pub enum Decoded {
+    /// Request sent by the client.
+    Request(eis::Request),
+    /// Event sent by the EIS implementation.
+    Event(ei::Event),
+}
+
+/// A message decoded by a [`Decoder`], along with its header.
+#[doc(hidden)]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct DecodedMessage {
+    /// Side that sent the message.
+    pub sender: Side,
+    /// ID of the object the message is for.
+    pub object_id: u64,
+    /// Interface of the object, like `ei_device`.
+    pub interface: String,
+    /// Opcode of the message.
+    pub opcode: u32,
+    /// Name of the request or event, like `frame`, or `UNKNOWN`.
+    pub name: &'static str,
+    /// The parsed message.
+    pub message: Decoded,
+    /// Number of file descriptors taken by the message.
+    pub fds: usize,
+    /// Objects created by the message, in order of their IDs.
+    pub new_objects: Vec<Object>,
+}
+
+impl DecodedMessage {
+    /// Returns the arguments of the message.
+    #[must_use]
+    pub fn args(&self) -> Vec<Arg<'_>> {
+        let mut args = Vec::new();
+        match &self.message {
+            Decoded::Request(request) => request.visit_args(&mut |arg| args.push(arg)),
+            Decoded::Event(event) => event.visit_args(&mut |arg| args.push(arg)),
+        }
+        args
+    }
+
+    /// Returns the arguments of the message, formatted like in the `REIS_DEBUG` output.
+    #[must_use]
+    pub fn format_args(&self) -> String {
+        wire::format_args(&self.args())
+    }
+}
+
+/// Decodes the messages of both sides of a connection.
+///
+/// Unlike a context, this tracks the objects created by either side, so the messages of a
+/// whole connection can be parsed from a capture, or while forwarding them between a client
+/// and an EIS implementation.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Decoder {
+    // Parses events
+    client: Backend,
+    // Parses requests
+    server: Backend,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    /// Creates a decoder for a new connection.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            client: Backend::with_transport(None, true, None),
+            server: Backend::with_transport(None, false, None),
+        }
+    }
+
+    /// Adds bytes and fds sent by `sender`.
+    ///
+    /// Messages with file descriptor arguments are only decoded if the fds are passed in.
+    pub fn receive(&self, sender: Side, bytes: &[u8], fds: impl IntoIterator<Item = OwnedFd>) {
+        let backend = match sender {
+            Side::Client => &self.server,
+            Side::Server => &self.client,
+        };
+        backend.receive(bytes, fds);
+    }
+
+    /// Returns the next complete message sent by `sender`.
+    ///
+    /// Objects created by the message are also added to the objects of the other side.
+    pub fn pending(&self, sender: Side) -> Option<PendingRequestResult<DecodedMessage>> {
+        match sender {
+            Side::Client => decode(
+                &self.server,
+                &self.client,
+                sender,
+                eis::Request::parse,
+                Decoded::Request,
+            ),
+            Side::Server => decode(
+                &self.client,
+                &self.server,
+                sender,
+                ei::Event::parse,
+                Decoded::Event,
+            ),
+        }
+    }
+}
+
+fn decode<T: MessageEnum>(
+    backend: &Backend,
+    other: &Backend,
+    sender: Side,
+    parse: fn(Object, u32, &mut ByteStream) -> Result<T, ParseError>,
+    wrap: fn(T) -> Decoded,
+) -> Option<PendingRequestResult<DecodedMessage>> {
+    let next_peer_id = backend.next_peer_id();
+    let (header, message) = match backend.pending_with_header(parse)? {
+        PendingRequestResult::Request(request) => request,
+        PendingRequestResult::ParseError(err) => {
+            return Some(PendingRequestResult::ParseError(err))
+        }
+        PendingRequestResult::InvalidObject(id) => {
+            return Some(PendingRequestResult::InvalidObject(id))
+        }
+    };
+
+    let new_objects = backend.peer_objects_since(next_peer_id);
+    for object in &new_objects {
+        other.insert_object(object.id(), object.interface().to_owned(), object.version());
+    }
+    let interface = backend.object_for_id(header.object_id).map_or_else(
+        || "UNKNOWN".to_owned(),
+        |object| object.interface().to_owned(),
+    );
+    let name = match sender {
+        Side::Client => eis::Request::op_name(&interface, header.opcode),
+        Side::Server => ei::Event::op_name(&interface, header.opcode),
+    }
+    .unwrap_or("UNKNOWN");
+    let mut fds = 0;
+    message.visit_args(&mut |arg| {
+        if matches!(arg, Arg::Fd(_)) {
+            fds += 1;
+        }
+    });
+
+    Some(PendingRequestResult::Request(DecodedMessage {
+        sender,
+        object_id: header.object_id,
+        interface,
+        opcode: header.opcode,
+        name,
+        message: wrap(message),
+        fds,
+        new_objects,
+    }))
+}
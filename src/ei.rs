@@ -1,6 +1,8 @@
 //! EI client implementation.
 //!
-//! Create a connection over a Unix socket with [`Context`].
+//! Create a connection over a Unix socket with [`Context`], or over another
+//! [`Transport`] with [`Context::with_transport`]. Without a transport, messages can also be
+//! passed in and out directly with [`Context::without_transport`].
 //!
 //! Client-side protocol bindings are exported here, and they consist of interface proxies (like
 //! [`device::Device`]) and event enums (like [`device::Event`]).
@@ -8,30 +10,34 @@
 use std::{
     env, io,
     os::unix::{
-        io::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
         net::UnixStream,
     },
     path::PathBuf,
+    sync::Arc,
 };
 
-use crate::{wire::Backend, PendingRequestResult};
+use crate::{
+    capture::CaptureRecorder, observer::MessageObserver, transport::ContextFd, wire::Backend,
+    PendingRequestResult, Transport,
+};
 
 // Re-export generate bindings
 pub use crate::eiproto_ei::*;
 
 /// A connection, seen from the client side.
 #[derive(Clone, Debug)]
-pub struct Context(pub(crate) Backend);
+pub struct Context(pub(crate) Backend, ContextFd);
 
 impl AsFd for Context {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.as_fd()
+        self.1.as_fd()
     }
 }
 
 impl AsRawFd for Context {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_fd().as_raw_fd()
+        self.1.as_fd().as_raw_fd()
     }
 }
 
@@ -54,7 +60,36 @@ impl Context {
     ///
     /// Will return `Err` if setting the socket to non-blocking mode fails.
     pub fn new(socket: UnixStream) -> io::Result<Self> {
-        Ok(Self(Backend::new(socket, true)?))
+        socket.set_nonblocking(true)?;
+        Ok(Self::with_transport(socket))
+    }
+
+    /// Creates a `Context` using another transport than a Unix socket.
+    ///
+    /// The transport must already be in non-blocking mode.
+    #[must_use]
+    pub fn with_transport<T: Transport>(transport: T) -> Self {
+        let transport: Arc<dyn Transport> = Arc::new(transport);
+        Self(
+            Backend::with_transport(Some(transport.clone()), true, None),
+            ContextFd::Transport(transport),
+        )
+    }
+
+    /// Creates a `Context` without a transport, which only exchanges messages with the server
+    /// through [`receive`](Self::receive) and [`take_outgoing`](Self::take_outgoing).
+    ///
+    /// [`read`](Self::read) and [`flush`](Self::flush) do nothing, and the file descriptor of
+    /// the context never becomes readable or writable.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if creating the file descriptor fails.
+    pub fn without_transport() -> io::Result<Self> {
+        Ok(Self(
+            Backend::with_transport(None, true, None),
+            ContextFd::idle()?,
+        ))
     }
 
     /// Connects to a socket based on the `LIBEI_SOCKET` environment variable, and creates
@@ -119,12 +154,70 @@ impl Context {
 
     /// Sends buffered messages. Call after you're finished with sending requests.
     ///
+    /// If the socket is full, the remaining messages stay buffered, and this still succeeds.
+    /// Check [`has_pending_writes`](Self::has_pending_writes), and flush again once the socket
+    /// is writable. The event sources and streams of this crate do this automatically.
+    ///
     /// # Errors
     ///
-    /// An error will be returned if sending the buffered messages fails.
+    /// An error will be returned if sending the buffered messages fails, or with `ENOBUFS` once
+    /// a message has been discarded because of the
+    /// [write buffer limit](Self::set_write_buffer_limit).
     pub fn flush(&self) -> rustix::io::Result<()> {
         self.0.flush()
     }
+
+    /// Returns `true` if buffered messages haven't been fully sent by
+    /// [`flush`](Self::flush), because the socket was full.
+    #[must_use]
+    pub fn has_pending_writes(&self) -> bool {
+        self.0.has_pending_writes()
+    }
+
+    /// Sets the maximum number of bytes that may be buffered, for example because the peer is
+    /// not reading.
+    ///
+    /// When a message doesn't fit, it is discarded, and every later [`flush`](Self::flush)
+    /// fails with `ENOBUFS`, so the connection should be closed. Defaults to `None`, which
+    /// doesn't limit the buffer.
+    pub fn set_write_buffer_limit(&self, limit: Option<usize>) {
+        self.0.set_write_buffer_limit(limit);
+    }
+
+    /// Adds bytes and file descriptors received from the server, as if they were
+    /// [read](Self::read) from the transport.
+    ///
+    /// Together with [`take_outgoing`](Self::take_outgoing), this allows a context created
+    /// [`without_transport`](Self::without_transport) to do the I/O outside of the context, for
+    /// example over an in-memory pipe in tests. Events are then returned by
+    /// [`pending_event`](Self::pending_event).
+    pub fn receive(&self, bytes: &[u8], fds: impl IntoIterator<Item = OwnedFd>) {
+        self.0.receive(bytes, fds);
+    }
+
+    /// Moves buffered messages into `bytes` and `fds`, instead of sending them with
+    /// [`flush`](Self::flush).
+    ///
+    /// The file descriptors must be sent along with the first of the bytes.
+    pub fn take_outgoing(&self, bytes: &mut Vec<u8>, fds: &mut Vec<OwnedFd>) {
+        self.0.take_outgoing(bytes, fds);
+    }
+
+    /// Sets a callback receiving each message sent and received, replacing any previous one.
+    ///
+    /// The observer is called from the thread sending or parsing the message, without holding
+    /// any lock of the context, so it may call back into it.
+    pub fn set_message_observer(&self, observer: Option<MessageObserver>) {
+        self.0.set_message_observer(observer);
+    }
+
+    /// Starts recording every message sent and received to a capture, or stops recording
+    /// with `None`.
+    ///
+    /// See the [`capture`](crate::capture) module for the format.
+    pub fn set_capture_recorder(&self, recorder: Option<CaptureRecorder>) {
+        self.0.set_capture_recorder(recorder);
+    }
 }
 
 #[doc(hidden)]
@@ -357,24 +357,22 @@ pub mod handshake {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::HandshakeVersion { version } => {
-                    args.push(version.as_arg());
+                    f(version.as_arg());
                 }
                 Self::InterfaceVersion { name, version } => {
-                    args.push(name.as_arg());
-                    args.push(version.as_arg());
+                    f(name.as_arg());
+                    f(version.as_arg());
                 }
                 Self::Connection { serial, connection } => {
-                    args.push(serial.as_arg());
-                    args.push(connection.as_arg());
+                    f(serial.as_arg());
+                    f(connection.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -669,35 +667,33 @@ pub mod connection {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Disconnected {
                     last_serial,
                     reason,
                     explanation,
                 } => {
-                    args.push(last_serial.as_arg());
-                    args.push(reason.as_arg());
-                    args.push(explanation.as_arg());
+                    f(last_serial.as_arg());
+                    f(reason.as_arg());
+                    f(explanation.as_arg());
                 }
                 Self::Seat { seat } => {
-                    args.push(seat.as_arg());
+                    f(seat.as_arg());
                 }
                 Self::InvalidObject {
                     last_serial,
                     invalid_id,
                 } => {
-                    args.push(last_serial.as_arg());
-                    args.push(invalid_id.as_arg());
+                    f(last_serial.as_arg());
+                    f(invalid_id.as_arg());
                 }
                 Self::Ping { ping } => {
-                    args.push(ping.as_arg());
+                    f(ping.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -813,16 +809,14 @@ pub mod callback {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Done { callback_data } => {
-                    args.push(callback_data.as_arg());
+                    f(callback_data.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -940,13 +934,11 @@ pub mod pingpong {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -1249,27 +1241,25 @@ pub mod seat {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Name { name } => {
-                    args.push(name.as_arg());
+                    f(name.as_arg());
                 }
                 Self::Capability { mask, interface } => {
-                    args.push(mask.as_arg());
-                    args.push(interface.as_arg());
+                    f(mask.as_arg());
+                    f(interface.as_arg());
                 }
                 Self::Done => {}
                 Self::Device { device } => {
-                    args.push(device.as_arg());
+                    f(device.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -1856,22 +1846,21 @@ pub mod device {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Name { name } => {
-                    args.push(name.as_arg());
+                    f(name.as_arg());
                 }
                 Self::DeviceType { device_type } => {
-                    args.push(device_type.as_arg());
+                    f(device_type.as_arg());
                 }
                 Self::Dimensions { width, height } => {
-                    args.push(width.as_arg());
-                    args.push(height.as_arg());
+                    f(width.as_arg());
+                    f(height.as_arg());
                 }
                 Self::Region {
                     offset_x,
@@ -1880,39 +1869,38 @@ pub mod device {
                     hight,
                     scale,
                 } => {
-                    args.push(offset_x.as_arg());
-                    args.push(offset_y.as_arg());
-                    args.push(width.as_arg());
-                    args.push(hight.as_arg());
-                    args.push(scale.as_arg());
+                    f(offset_x.as_arg());
+                    f(offset_y.as_arg());
+                    f(width.as_arg());
+                    f(hight.as_arg());
+                    f(scale.as_arg());
                 }
                 Self::Interface { object } => {
-                    args.push(object.as_arg());
+                    f(object.as_arg());
                 }
                 Self::Done => {}
                 Self::Resumed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Paused { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::StartEmulating { serial, sequence } => {
-                    args.push(serial.as_arg());
-                    args.push(sequence.as_arg());
+                    f(serial.as_arg());
+                    f(sequence.as_arg());
                 }
                 Self::StopEmulating { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Frame { serial, timestamp } => {
-                    args.push(serial.as_arg());
-                    args.push(timestamp.as_arg());
+                    f(serial.as_arg());
+                    f(timestamp.as_arg());
                 }
                 Self::RegionMappingId { mapping_id } => {
-                    args.push(mapping_id.as_arg());
+                    f(mapping_id.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2095,20 +2083,18 @@ pub mod pointer {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::MotionRelative { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2293,20 +2279,18 @@ pub mod pointer_absolute {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::MotionAbsolute { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2614,29 +2598,27 @@ pub mod scroll {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Scroll { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::ScrollDiscrete { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::ScrollStop { x, y, is_cancel } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
-                    args.push(is_cancel.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
+                    f(is_cancel.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2833,20 +2815,18 @@ pub mod button {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Button { button, state } => {
-                    args.push(button.as_arg());
-                    args.push(state.as_arg());
+                    f(button.as_arg());
+                    f(state.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3158,25 +3138,24 @@ pub mod keyboard {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Keymap {
                     keymap_type,
                     size,
                     keymap,
                 } => {
-                    args.push(keymap_type.as_arg());
-                    args.push(size.as_arg());
-                    args.push(keymap.as_arg());
+                    f(keymap_type.as_arg());
+                    f(size.as_arg());
+                    f(keymap.as_arg());
                 }
                 Self::Key { key, state } => {
-                    args.push(key.as_arg());
-                    args.push(state.as_arg());
+                    f(key.as_arg());
+                    f(state.as_arg());
                 }
                 Self::Modifiers {
                     serial,
@@ -3185,15 +3164,14 @@ pub mod keyboard {
                     latched,
                     group,
                 } => {
-                    args.push(serial.as_arg());
-                    args.push(depressed.as_arg());
-                    args.push(locked.as_arg());
-                    args.push(latched.as_arg());
-                    args.push(group.as_arg());
+                    f(serial.as_arg());
+                    f(depressed.as_arg());
+                    f(locked.as_arg());
+                    f(latched.as_arg());
+                    f(group.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3560,32 +3538,30 @@ pub mod touchscreen {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Down { touchid, x, y } => {
-                    args.push(touchid.as_arg());
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(touchid.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::Motion { touchid, x, y } => {
-                    args.push(touchid.as_arg());
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(touchid.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::Up { touchid } => {
-                    args.push(touchid.as_arg());
+                    f(touchid.as_arg());
                 }
                 Self::Cancel { touchid } => {
-                    args.push(touchid.as_arg());
+                    f(touchid.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3849,23 +3825,21 @@ pub mod text {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Destroyed { serial } => {
-                    args.push(serial.as_arg());
+                    f(serial.as_arg());
                 }
                 Self::Keysym { keysym, state } => {
-                    args.push(keysym.as_arg());
-                    args.push(state.as_arg());
+                    f(keysym.as_arg());
+                    f(state.as_arg());
                 }
                 Self::Utf8 { text } => {
-                    args.push(text.as_arg());
+                    f(text.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3977,21 +3951,21 @@ impl Event {
 }
 
 impl wire::MessageEnum for Event {
-    fn args(&self) -> Vec<wire::Arg<'_>> {
+    fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
         match self {
-            Self::Handshake(_, x) => x.args(),
-            Self::Connection(_, x) => x.args(),
-            Self::Callback(_, x) => x.args(),
-            Self::Pingpong(_, x) => x.args(),
-            Self::Seat(_, x) => x.args(),
-            Self::Device(_, x) => x.args(),
-            Self::Pointer(_, x) => x.args(),
-            Self::PointerAbsolute(_, x) => x.args(),
-            Self::Scroll(_, x) => x.args(),
-            Self::Button(_, x) => x.args(),
-            Self::Keyboard(_, x) => x.args(),
-            Self::Touchscreen(_, x) => x.args(),
-            Self::Text(_, x) => x.args(),
+            Self::Handshake(_, x) => x.visit_args(f),
+            Self::Connection(_, x) => x.visit_args(f),
+            Self::Callback(_, x) => x.visit_args(f),
+            Self::Pingpong(_, x) => x.visit_args(f),
+            Self::Seat(_, x) => x.visit_args(f),
+            Self::Device(_, x) => x.visit_args(f),
+            Self::Pointer(_, x) => x.visit_args(f),
+            Self::PointerAbsolute(_, x) => x.visit_args(f),
+            Self::Scroll(_, x) => x.visit_args(f),
+            Self::Button(_, x) => x.visit_args(f),
+            Self::Keyboard(_, x) => x.visit_args(f),
+            Self::Touchscreen(_, x) => x.visit_args(f),
+            Self::Text(_, x) => x.visit_args(f),
         }
     }
 }
@@ -353,27 +353,25 @@ pub mod handshake {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::HandshakeVersion { version } => {
-                    args.push(version.as_arg());
+                    f(version.as_arg());
                 }
                 Self::Finish => {}
                 Self::ContextType { context_type } => {
-                    args.push(context_type.as_arg());
+                    f(context_type.as_arg());
                 }
                 Self::Name { name } => {
-                    args.push(name.as_arg());
+                    f(name.as_arg());
                 }
                 Self::InterfaceVersion { name, version } => {
-                    args.push(name.as_arg());
-                    args.push(version.as_arg());
+                    f(name.as_arg());
+                    f(version.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -672,17 +670,15 @@ pub mod connection {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Sync { callback } => {
-                    args.push(callback.as_arg());
+                    f(callback.as_arg());
                 }
                 Self::Disconnect => {}
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -800,13 +796,11 @@ pub mod callback {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -922,16 +916,14 @@ pub mod pingpong {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Done { callback_data } => {
-                    args.push(callback_data.as_arg());
+                    f(callback_data.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -1244,20 +1236,18 @@ pub mod seat {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Bind { capabilities } => {
-                    args.push(capabilities.as_arg());
+                    f(capabilities.as_arg());
                 }
                 Self::RequestDevice { capabilities } => {
-                    args.push(capabilities.as_arg());
+                    f(capabilities.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -1867,32 +1857,30 @@ pub mod device {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::StartEmulating {
                     last_serial,
                     sequence,
                 } => {
-                    args.push(last_serial.as_arg());
-                    args.push(sequence.as_arg());
+                    f(last_serial.as_arg());
+                    f(sequence.as_arg());
                 }
                 Self::StopEmulating { last_serial } => {
-                    args.push(last_serial.as_arg());
+                    f(last_serial.as_arg());
                 }
                 Self::Frame {
                     last_serial,
                     timestamp,
                 } => {
-                    args.push(last_serial.as_arg());
-                    args.push(timestamp.as_arg());
+                    f(last_serial.as_arg());
+                    f(timestamp.as_arg());
                 }
                 Self::Ready => {}
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2073,18 +2061,16 @@ pub mod pointer {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::MotionRelative { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2267,18 +2253,16 @@ pub mod pointer_absolute {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::MotionAbsolute { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2584,27 +2568,25 @@ pub mod scroll {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Scroll { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::ScrollDiscrete { x, y } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::ScrollStop { x, y, is_cancel } => {
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
-                    args.push(is_cancel.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
+                    f(is_cancel.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -2799,18 +2781,16 @@ pub mod button {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Button { button, state } => {
-                    args.push(button.as_arg());
-                    args.push(state.as_arg());
+                    f(button.as_arg());
+                    f(state.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3124,18 +3104,16 @@ pub mod keyboard {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Key { key, state } => {
-                    args.push(key.as_arg());
-                    args.push(state.as_arg());
+                    f(key.as_arg());
+                    f(state.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3500,30 +3478,28 @@ pub mod touchscreen {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Down { touchid, x, y } => {
-                    args.push(touchid.as_arg());
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(touchid.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::Motion { touchid, x, y } => {
-                    args.push(touchid.as_arg());
-                    args.push(x.as_arg());
-                    args.push(y.as_arg());
+                    f(touchid.as_arg());
+                    f(x.as_arg());
+                    f(y.as_arg());
                 }
                 Self::Up { touchid } => {
-                    args.push(touchid.as_arg());
+                    f(touchid.as_arg());
                 }
                 Self::Cancel { touchid } => {
-                    args.push(touchid.as_arg());
+                    f(touchid.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3785,21 +3761,19 @@ pub mod text {
             unreachable_code,
             unreachable_patterns
         )]
-        pub(super) fn args(&self) -> Vec<wire::Arg<'_>> {
+        pub(super) fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
             use crate::{wire::OwnedArg, Interface};
-            let mut args = Vec::new();
             match self {
                 Self::Release => {}
                 Self::Keysym { keysym, state } => {
-                    args.push(keysym.as_arg());
-                    args.push(state.as_arg());
+                    f(keysym.as_arg());
+                    f(state.as_arg());
                 }
                 Self::Utf8 { text } => {
-                    args.push(text.as_arg());
+                    f(text.as_arg());
                 }
                 _ => unreachable!(),
             }
-            args
         }
     }
 }
@@ -3911,21 +3885,21 @@ impl Request {
 }
 
 impl wire::MessageEnum for Request {
-    fn args(&self) -> Vec<wire::Arg<'_>> {
+    fn visit_args<'a>(&'a self, f: &mut dyn FnMut(wire::Arg<'a>)) {
         match self {
-            Self::Handshake(_, x) => x.args(),
-            Self::Connection(_, x) => x.args(),
-            Self::Callback(_, x) => x.args(),
-            Self::Pingpong(_, x) => x.args(),
-            Self::Seat(_, x) => x.args(),
-            Self::Device(_, x) => x.args(),
-            Self::Pointer(_, x) => x.args(),
-            Self::PointerAbsolute(_, x) => x.args(),
-            Self::Scroll(_, x) => x.args(),
-            Self::Button(_, x) => x.args(),
-            Self::Keyboard(_, x) => x.args(),
-            Self::Touchscreen(_, x) => x.args(),
-            Self::Text(_, x) => x.args(),
+            Self::Handshake(_, x) => x.visit_args(f),
+            Self::Connection(_, x) => x.visit_args(f),
+            Self::Callback(_, x) => x.visit_args(f),
+            Self::Pingpong(_, x) => x.visit_args(f),
+            Self::Seat(_, x) => x.visit_args(f),
+            Self::Device(_, x) => x.visit_args(f),
+            Self::Pointer(_, x) => x.visit_args(f),
+            Self::PointerAbsolute(_, x) => x.visit_args(f),
+            Self::Scroll(_, x) => x.visit_args(f),
+            Self::Button(_, x) => x.visit_args(f),
+            Self::Keyboard(_, x) => x.visit_args(f),
+            Self::Touchscreen(_, x) => x.visit_args(f),
+            Self::Text(_, x) => x.visit_args(f),
         }
     }
 }
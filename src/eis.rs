@@ -8,13 +8,17 @@
 use std::{
     env, fmt, fs, io,
     os::unix::{
-        io::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
         net::{UnixListener, UnixStream},
     },
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-use crate::{util, wire::Backend, PendingRequestResult};
+use crate::{
+    capture::CaptureRecorder, observer::MessageObserver, transport::ContextFd, util, wire::Backend,
+    PendingRequestResult, Transport,
+};
 
 // Re-export generate bindings
 pub use crate::eiproto_eis::*;
@@ -144,19 +148,47 @@ impl AsRawFd for Listener {
     }
 }
 
+/// Credentials of the client process, read with `SO_PEERCRED` when the connection is created.
+///
+/// These are the credentials the client had when it connected, which may be used to apply
+/// policy or to ask the user whether the client may emulate input. If the client already
+/// exited, its PID may have been reused by another process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PeerCredentials {
+    /// Process ID of the client.
+    pub pid: i32,
+    /// User ID of the client.
+    pub uid: u32,
+    /// Group ID of the client.
+    pub gid: u32,
+}
+
+impl PeerCredentials {
+    pub(crate) fn for_socket(socket: &UnixStream) -> Option<Self> {
+        // XXX Also read a pidfd with `SO_PEERPIDFD`, once rustix supports it
+        let ucred = rustix::net::sockopt::socket_peercred(socket).ok()?;
+        Some(Self {
+            pid: ucred.pid.as_raw_nonzero().get(),
+            uid: ucred.uid.as_raw(),
+            gid: ucred.gid.as_raw(),
+        })
+    }
+}
+
 /// A connection, seen from the server side.
 #[derive(Clone, Debug)]
-pub struct Context(pub(crate) Backend);
+pub struct Context(pub(crate) Backend, ContextFd);
 
 impl AsFd for Context {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.as_fd()
+        self.1.as_fd()
     }
 }
 
 impl AsRawFd for Context {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_fd().as_raw_fd()
+        self.1.as_fd().as_raw_fd()
     }
 }
 
@@ -179,7 +211,54 @@ impl Context {
     ///
     /// Will return `Err` if setting the socket to non-blocking mode fails.
     pub fn new(socket: UnixStream) -> io::Result<Self> {
-        Ok(Self(Backend::new(socket, false)?))
+        socket.set_nonblocking(true)?;
+        let peer_credentials = PeerCredentials::for_socket(&socket);
+        Ok(Self::from_transport(Arc::new(socket), peer_credentials))
+    }
+
+    /// Creates a `Context` using another transport than a Unix socket.
+    ///
+    /// The transport must already be in non-blocking mode. The credentials of the client
+    /// aren't known, so [`peer_credentials`](Self::peer_credentials) returns `None`.
+    #[must_use]
+    pub fn with_transport<T: Transport>(transport: T) -> Self {
+        Self::from_transport(Arc::new(transport), None)
+    }
+
+    /// Creates a `Context` without a transport, which only exchanges messages with the client
+    /// through [`receive`](Self::receive) and [`take_outgoing`](Self::take_outgoing).
+    ///
+    /// [`read`](Self::read) and [`flush`](Self::flush) do nothing, and the file descriptor of
+    /// the context never becomes readable or writable.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if creating the file descriptor fails.
+    pub fn without_transport() -> io::Result<Self> {
+        Ok(Self(
+            Backend::with_transport(None, false, None),
+            ContextFd::idle()?,
+        ))
+    }
+
+    fn from_transport(
+        transport: Arc<dyn Transport>,
+        peer_credentials: Option<PeerCredentials>,
+    ) -> Self {
+        Self(
+            Backend::with_transport(Some(transport.clone()), false, peer_credentials),
+            ContextFd::Transport(transport),
+        )
+    }
+
+    /// Returns the credentials of the client process.
+    ///
+    /// Returns `None` if they couldn't be read from the socket, or the context was created
+    /// [`with_transport`](Self::with_transport) or
+    /// [`without_transport`](Self::without_transport).
+    #[must_use]
+    pub fn peer_credentials(&self) -> Option<&PeerCredentials> {
+        self.0.peer_credentials()
     }
 
     /// Reads any pending data on the socket into the internal buffer.
@@ -208,12 +287,70 @@ impl Context {
 
     /// Sends buffered messages. Call after you're finished with sending events.
     ///
+    /// If the socket is full, the remaining messages stay buffered, and this still succeeds.
+    /// Check [`has_pending_writes`](Self::has_pending_writes), and flush again once the socket
+    /// is writable. The request sources and streams of this crate do this automatically.
+    ///
     /// # Errors
     ///
-    /// An error will be returned if sending the buffered messages fails.
+    /// An error will be returned if sending the buffered messages fails, or with `ENOBUFS` once
+    /// a message has been discarded because of the
+    /// [write buffer limit](Self::set_write_buffer_limit).
     pub fn flush(&self) -> rustix::io::Result<()> {
         self.0.flush()
     }
+
+    /// Returns `true` if buffered messages haven't been fully sent by
+    /// [`flush`](Self::flush), because the socket was full.
+    #[must_use]
+    pub fn has_pending_writes(&self) -> bool {
+        self.0.has_pending_writes()
+    }
+
+    /// Sets the maximum number of bytes that may be buffered, for example because the peer is
+    /// not reading.
+    ///
+    /// When a message doesn't fit, it is discarded, and every later [`flush`](Self::flush)
+    /// fails with `ENOBUFS`, so the connection should be closed. Defaults to `None`, which
+    /// doesn't limit the buffer.
+    pub fn set_write_buffer_limit(&self, limit: Option<usize>) {
+        self.0.set_write_buffer_limit(limit);
+    }
+
+    /// Adds bytes and file descriptors received from the client, as if they were
+    /// [read](Self::read) from the transport.
+    ///
+    /// Together with [`take_outgoing`](Self::take_outgoing), this allows a context created
+    /// [`without_transport`](Self::without_transport) to do the I/O outside of the context, for
+    /// example over an in-memory pipe in tests. Requests are then returned by
+    /// [`pending_request`](Self::pending_request).
+    pub fn receive(&self, bytes: &[u8], fds: impl IntoIterator<Item = OwnedFd>) {
+        self.0.receive(bytes, fds);
+    }
+
+    /// Moves buffered messages into `bytes` and `fds`, instead of sending them with
+    /// [`flush`](Self::flush).
+    ///
+    /// The file descriptors must be sent along with the first of the bytes.
+    pub fn take_outgoing(&self, bytes: &mut Vec<u8>, fds: &mut Vec<OwnedFd>) {
+        self.0.take_outgoing(bytes, fds);
+    }
+
+    /// Sets a callback receiving each message sent and received, replacing any previous one.
+    ///
+    /// The observer is called from the thread sending or parsing the message, without holding
+    /// any lock of the context, so it may call back into it.
+    pub fn set_message_observer(&self, observer: Option<MessageObserver>) {
+        self.0.set_message_observer(observer);
+    }
+
+    /// Starts recording every message sent and received to a capture, or stops recording
+    /// with `None`.
+    ///
+    /// See the [`capture`](crate::capture) module for the format.
+    pub fn set_capture_recorder(&self, recorder: Option<CaptureRecorder>) {
+        self.0.set_capture_recorder(recorder);
+    }
 }
 
 #[doc(hidden)]
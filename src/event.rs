@@ -750,7 +750,7 @@ impl EiEventConverter {
 }
 
 /// A [`Region`] defines the area that is accessible by a device.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Region {
     /// X offset in logical pixels.
     pub x: u32,
@@ -766,6 +766,20 @@ pub struct Region {
     pub mapping_id: Option<String>,
 }
 
+impl Region {
+    /// Returns `true` if the point at `x`/`y` in logical pixels lies within this region.
+    ///
+    /// Like libei, the lower bounds are inclusive and the upper bounds exclusive.
+    #[must_use]
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let (x, y) = (f64::from(x), f64::from(y));
+        x >= f64::from(self.x)
+            && x < f64::from(self.x) + f64::from(self.width)
+            && y >= f64::from(self.y)
+            && y < f64::from(self.y) + f64::from(self.height)
+    }
+}
+
 /// A keymap from the server.
 #[derive(Debug)]
 pub struct Keymap {
@@ -1545,9 +1559,15 @@ impl Iterator for EiConvertEventIterator {
             if let Some(event) = self.converter.next_event() {
                 return Some(Ok(event));
             }
-            if let Err(err) = util::poll_readable(&self.context) {
+            let writable = self.context.has_pending_writes();
+            if let Err(err) = util::poll_readable(&self.context, writable, None) {
                 return Some(Err(err.into()));
             }
+            if writable {
+                if let Err(err) = self.context.flush() {
+                    return Some(Err(io::Error::from(err).into()));
+                }
+            }
             match self.context.read() {
                 Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
                 Err(err) => return Some(Err(err.into())),
@@ -2,8 +2,13 @@
 //!
 //! The generic [`EiHandshaker`] can be used in async and sync code.
 
-use crate::{ei, eis, util, Error, PendingRequestResult};
-use std::{collections::HashMap, error, fmt, mem, sync::OnceLock};
+use crate::{ei, eis, util, Error, Interface, PendingRequestResult};
+use std::{
+    collections::HashMap,
+    error, fmt, io, mem,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
 
 fn interfaces() -> &'static HashMap<&'static str, u32> {
     fn iface<I: ei::Interface>() -> (&'static str, u32) {
@@ -30,6 +35,77 @@ fn interfaces() -> &'static HashMap<&'static str, u32> {
     })
 }
 
+/// Set of interfaces, with their maximum versions, to support in a handshake.
+///
+/// Only interfaces known to reis can be included, at most at the version implemented by reis.
+/// The `ei_connection`, `ei_callback` and `ei_pingpong` interfaces are required by the
+/// protocol and the handshake will fail without them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceVersions(HashMap<&'static str, u32>);
+
+impl InterfaceVersions {
+    /// Creates a set of all interfaces known to reis, at their highest version.
+    #[must_use]
+    pub fn all() -> Self {
+        Self(interfaces().clone())
+    }
+
+    /// Removes the named interface from the set.
+    #[must_use]
+    pub fn without_interface(mut self, interface: &str) -> Self {
+        self.0.remove(interface);
+        self
+    }
+
+    /// Limits the named interface to at most `version`.
+    ///
+    /// A version of `0` removes the interface.
+    #[must_use]
+    pub fn with_max_version(mut self, interface: &str, version: u32) -> Self {
+        if version == 0 {
+            self.0.remove(interface);
+        } else if let Some(current) = self.0.get_mut(interface) {
+            *current = (*current).min(version);
+        }
+        self
+    }
+
+    /// Returns the version of the named interface, if it is in the set.
+    #[must_use]
+    pub fn version(&self, interface: &str) -> Option<u32> {
+        self.0.get(interface).copied()
+    }
+
+    /// Returns an iterator over the interfaces and their versions.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u32)> + '_ {
+        self.0
+            .iter()
+            .map(|(interface, version)| (*interface, *version))
+    }
+}
+
+impl Default for InterfaceVersions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// Collects interfaces with their versions. Interfaces unknown to reis are ignored, and
+/// versions are limited to those implemented by reis.
+impl<'a> FromIterator<(&'a str, u32)> for InterfaceVersions {
+    fn from_iter<T: IntoIterator<Item = (&'a str, u32)>>(iter: T) -> Self {
+        Self(
+            iter.into_iter()
+                .filter(|(_, version)| *version > 0)
+                .filter_map(|(interface, version)| {
+                    let (name, max_version) = interfaces().get_key_value(interface)?;
+                    Some((*name, version.min(*max_version)))
+                })
+                .collect(),
+        )
+    }
+}
+
 /// Handshake response.
 #[derive(Clone, Debug)]
 pub struct HandshakeResp {
@@ -37,6 +113,8 @@ pub struct HandshakeResp {
     pub connection: ei::Connection,
     /// Serial number of `ei_handshake.connection`.
     pub serial: u32,
+    /// Version of `ei_handshake` negotiated in the handshake.
+    pub handshake_version: u32,
     /// Interfaces along with their versions negotiated in the handshake.
     pub negotiated_interfaces: HashMap<String, u32>,
 }
@@ -52,6 +130,16 @@ pub enum HandshakeError {
     MissingInterface,
     /// Duplicate event.
     DuplicateEvent,
+    /// Handshake version that is `0` or higher than the one offered by the server.
+    InvalidHandshakeVersion(u32),
+    /// Handshake request before `ei_handshake.handshake_version`.
+    MissingHandshakeVersion,
+    /// Handshake did not complete within the configured timeout.
+    Timeout,
+    /// Client was rejected by the authorizer of [`EisHandshaker`].
+    Rejected(eis::connection::DisconnectReason),
+    /// Authorizer of [`EisHandshaker`] deferred its decision, but there is no way to resolve it.
+    Deferred,
 }
 
 impl fmt::Display for HandshakeError {
@@ -61,6 +149,13 @@ impl fmt::Display for HandshakeError {
             Self::NonHandshakeEvent => write!(f, "non-handshake event during handshake"),
             Self::MissingInterface => write!(f, "missing required interface"),
             Self::DuplicateEvent => write!(f, "duplicate event during handshake"),
+            Self::InvalidHandshakeVersion(version) => {
+                write!(f, "unsupported handshake version {version}")
+            }
+            Self::MissingHandshakeVersion => write!(f, "handshake version not sent first"),
+            Self::Timeout => write!(f, "handshake timed out"),
+            Self::Rejected(reason) => write!(f, "client rejected with reason {reason:?}"),
+            Self::Deferred => write!(f, "authorization deferred with no way to resolve it"),
         }
     }
 }
@@ -71,20 +166,51 @@ impl error::Error for HandshakeError {}
 pub struct EiHandshaker<'a> {
     name: &'a str,
     context_type: ei::handshake::ContextType,
+    interfaces: InterfaceVersions,
+    timeout: Option<Duration>,
+    handshake_version: Option<u32>,
     negotiated_interfaces: HashMap<String, u32>,
 }
 
 impl<'a> EiHandshaker<'a> {
     /// Creates a client-side handshaker.
+    ///
+    /// All interfaces known to reis are advertised, at their highest version.
     #[must_use]
     pub fn new(name: &'a str, context_type: ei::handshake::ContextType) -> Self {
         Self {
             name,
             context_type,
+            interfaces: InterfaceVersions::all(),
+            timeout: None,
+            handshake_version: None,
             negotiated_interfaces: HashMap::new(),
         }
     }
 
+    /// Sets how long the handshake may take before failing with [`HandshakeError::Timeout`].
+    ///
+    /// This is enforced by the functions driving the handshake, like
+    /// [`ei_handshake_blocking_with`]. By default there is no timeout.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the timeout set with [`EiHandshaker::with_timeout`].
+    #[must_use]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the interfaces and maximum versions advertised to the server.
+    #[must_use]
+    pub fn with_interfaces(mut self, interfaces: InterfaceVersions) -> Self {
+        self.interfaces = interfaces;
+        self
+    }
+
     /// Handles the given event, possibly returning a filled handshake response.
     ///
     /// # Errors
@@ -98,12 +224,21 @@ impl<'a> EiHandshaker<'a> {
             return Err(HandshakeError::NonHandshakeEvent);
         };
         match event {
-            ei::handshake::Event::HandshakeVersion { version: _ } => {
-                handshake.handshake_version(1);
+            ei::handshake::Event::HandshakeVersion { version } => {
+                if self.handshake_version.is_some() {
+                    return Err(HandshakeError::DuplicateEvent);
+                }
+                if version == 0 {
+                    return Err(HandshakeError::InvalidHandshakeVersion(version));
+                }
+                let version = version.min(ei::Handshake::VERSION);
+                self.handshake_version = Some(version);
+
+                handshake.handshake_version(version);
                 handshake.name(self.name);
                 handshake.context_type(self.context_type);
-                for (interface, version) in interfaces() {
-                    handshake.interface_version(interface, *version);
+                for (interface, version) in self.interfaces.iter() {
+                    handshake.interface_version(interface, version);
                 }
                 handshake.finish();
 
@@ -121,6 +256,9 @@ impl<'a> EiHandshaker<'a> {
             ei::handshake::Event::Connection { connection, serial } => Ok(Some(HandshakeResp {
                 connection,
                 serial,
+                handshake_version: self
+                    .handshake_version
+                    .ok_or(HandshakeError::MissingHandshakeVersion)?,
                 negotiated_interfaces: mem::take(&mut self.negotiated_interfaces),
             })),
         }
@@ -147,9 +285,28 @@ pub fn ei_handshake_blocking(
     name: &str,
     context_type: ei::handshake::ContextType,
 ) -> Result<HandshakeResp, Error> {
-    let mut handshaker = EiHandshaker::new(name, context_type);
+    ei_handshake_blocking_with(context, EiHandshaker::new(name, context_type))
+}
+
+/// Executes the handshake in blocking mode, using the given handshaker.
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error or a protocol violation.
+pub fn ei_handshake_blocking_with(
+    context: &ei::Context,
+    mut handshaker: EiHandshaker<'_>,
+) -> Result<HandshakeResp, Error> {
+    let deadline = handshaker.timeout().map(|timeout| Instant::now() + timeout);
     loop {
-        util::poll_readable(context)?;
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        let writable = context.has_pending_writes();
+        if !util::poll_readable(context, writable, timeout)? {
+            return Err(HandshakeError::Timeout.into());
+        }
+        if writable {
+            context.flush().map_err(io::Error::from)?;
+        }
         context.read()?;
         while let Some(result) = context.pending_event() {
             let request = request_result(result)?;
@@ -169,17 +326,74 @@ pub struct EisHandshakeResp {
     pub name: Option<String>,
     /// Context type of connection.
     pub context_type: eis::handshake::ContextType,
+    /// Version of `ei_handshake` negotiated in the handshake.
+    pub handshake_version: u32,
     /// Interfaces along with their versions negotiated in the handshake.
     pub negotiated_interfaces: HashMap<String, u32>,
 }
 
-/// Implementation of the EI protocol handshake on the server side.
+/// Information about a connecting client, passed to the authorizer of an [`EisHandshaker`].
 #[derive(Debug)]
+pub struct ClientInfo<'a> {
+    /// Name of client.
+    pub name: Option<&'a str>,
+    /// Context type of connection.
+    pub context_type: eis::handshake::ContextType,
+    /// Interfaces along with their versions negotiated in the handshake.
+    pub negotiated_interfaces: &'a HashMap<String, u32>,
+    /// Credentials of the client process.
+    pub peer_credentials: Option<&'a eis::PeerCredentials>,
+}
+
+/// Decision whether a client may connect, made by the authorizer of an [`EisHandshaker`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Authorization {
+    /// Finish the handshake.
+    Accept,
+    /// Disconnect the client.
+    Reject {
+        /// Reason sent to the client. Should be something other than
+        /// [`DisconnectReason::Disconnected`](eis::connection::DisconnectReason::Disconnected).
+        reason: eis::connection::DisconnectReason,
+        /// Optional explanation sent to the client.
+        explanation: Option<String>,
+    },
+    /// Decide later, for example after prompting the user, with
+    /// [`EisHandshaker::authorize`].
+    Defer,
+}
+
+type Authorizer = Box<dyn FnMut(&ClientInfo<'_>) -> Authorization + Send>;
+
+/// Implementation of the EI protocol handshake on the server side.
 pub struct EisHandshaker {
+    context: eis::Context,
     name: Option<String>,
     context_type: Option<eis::handshake::ContextType>,
+    handshake_version: Option<u32>,
+    interfaces: InterfaceVersions,
+    timeout: Option<Duration>,
     negotiated_interfaces: HashMap<String, u32>,
     initial_serial: u32,
+    authorizer: Option<Authorizer>,
+    // Set once `finish` is received
+    finished: Option<eis::Handshake>,
+}
+
+impl fmt::Debug for EisHandshaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EisHandshaker")
+            .field("context", &self.context)
+            .field("name", &self.name)
+            .field("context_type", &self.context_type)
+            .field("handshake_version", &self.handshake_version)
+            .field("interfaces", &self.interfaces)
+            .field("timeout", &self.timeout)
+            .field("negotiated_interfaces", &self.negotiated_interfaces)
+            .field("initial_serial", &self.initial_serial)
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
 }
 
 impl EisHandshaker {
@@ -187,16 +401,128 @@ impl EisHandshaker {
     #[must_use]
     pub fn new(context: &eis::Context, initial_serial: u32) -> Self {
         let handshake = context.handshake();
-        handshake.handshake_version(1);
+        handshake.handshake_version(eis::Handshake::VERSION);
         // XXX error handling?
         let _ = context.flush();
 
         Self {
+            context: context.clone(),
             initial_serial,
             name: None,
             context_type: None,
+            handshake_version: None,
+            interfaces: InterfaceVersions::all(),
+            timeout: None,
             negotiated_interfaces: HashMap::new(),
+            authorizer: None,
+            finished: None,
+        }
+    }
+
+    /// Sets the interfaces and maximum versions supported by the server.
+    ///
+    /// Interfaces outside of this set are not negotiated, even if the client supports them.
+    /// Defaults to all interfaces known to reis. This must be called before the client
+    /// finishes the handshake to have an effect.
+    pub fn set_interfaces(&mut self, interfaces: InterfaceVersions) {
+        self.interfaces = interfaces;
+    }
+
+    /// Sets how long the client may take to finish the handshake.
+    ///
+    /// This is enforced by the event source driving the handshake, like the `calloop`
+    /// `EisRequestSource`, which fails with [`HandshakeError::Timeout`]. Time spent waiting for
+    /// a deferred authorization counts towards it. By default there is no timeout.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Returns the timeout set with [`EisHandshaker::set_timeout`].
+    #[must_use]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets a policy deciding whether a client may connect.
+    ///
+    /// It is called when the client finishes the handshake, before `ei_handshake.connection`
+    /// is sent. Without an authorizer, every client is accepted.
+    pub fn set_authorizer<F>(&mut self, authorizer: F)
+    where
+        F: FnMut(&ClientInfo<'_>) -> Authorization + Send + 'static,
+    {
+        self.authorizer = Some(Box::new(authorizer));
+    }
+
+    /// Returns `true` if the authorizer has deferred its decision, and the handshake waits for
+    /// [`authorize`](Self::authorize).
+    #[must_use]
+    pub fn is_deferred(&self) -> bool {
+        self.finished.is_some()
+    }
+
+    /// Completes a handshake that the authorizer deferred, possibly returning a filled
+    /// handshake response.
+    ///
+    /// Does nothing if the handshake is not deferred, or if `authorization` is
+    /// [`Authorization::Defer`] again.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`HandshakeError::Rejected`] if the client was rejected.
+    pub fn authorize(
+        &mut self,
+        authorization: Authorization,
+    ) -> Result<Option<EisHandshakeResp>, HandshakeError> {
+        let Some(handshake) = self.finished.clone() else {
+            return Ok(None);
+        };
+        self.apply_authorization(&handshake, authorization)
+    }
+
+    fn apply_authorization(
+        &mut self,
+        handshake: &eis::Handshake,
+        authorization: Authorization,
+    ) -> Result<Option<EisHandshakeResp>, HandshakeError> {
+        let reject = match authorization {
+            Authorization::Defer => {
+                self.finished = Some(handshake.clone());
+                return Ok(None);
+            }
+            Authorization::Accept => None,
+            Authorization::Reject {
+                reason,
+                explanation,
+            } => Some((reason, explanation)),
+        };
+        self.finished = None;
+
+        for (interface, version) in &self.negotiated_interfaces {
+            handshake.interface_version(interface, *version);
+        }
+        let connection = handshake.connection(self.initial_serial, 1);
+
+        if let Some((reason, explanation)) = reject {
+            connection.disconnected(self.initial_serial, reason, explanation.as_deref());
+            let _ = self.context.flush();
+            return Err(HandshakeError::Rejected(reason));
         }
+
+        Ok(Some(EisHandshakeResp {
+            connection,
+            name: self.name.clone(),
+            context_type: self.context_type(),
+            // Set before `finish` is accepted
+            handshake_version: self.handshake_version.unwrap_or(1),
+            negotiated_interfaces: mem::take(&mut self.negotiated_interfaces),
+        }))
+    }
+
+    fn context_type(&self) -> eis::handshake::ContextType {
+        // Protocol spec says `context_type` is optional, defaults to receiver
+        self.context_type
+            .unwrap_or(ei::handshake::ContextType::Receiver)
     }
 
     /// Handles the given request, possibly returning a filled handshake response.
@@ -211,8 +537,24 @@ impl EisHandshaker {
         let eis::Request::Handshake(handshake, request) = request else {
             return Err(HandshakeError::NonHandshakeEvent);
         };
+        if self.finished.is_some() {
+            return Err(HandshakeError::DuplicateEvent);
+        }
+        // The client must send `handshake_version` before any other request
+        match (&request, self.handshake_version) {
+            (eis::handshake::Request::HandshakeVersion { .. }, Some(_)) => {
+                return Err(HandshakeError::DuplicateEvent);
+            }
+            (eis::handshake::Request::HandshakeVersion { .. }, None) | (_, Some(_)) => {}
+            (_, None) => return Err(HandshakeError::MissingHandshakeVersion),
+        }
         match request {
-            eis::handshake::Request::HandshakeVersion { version: _ } => {}
+            eis::handshake::Request::HandshakeVersion { version } => {
+                if version == 0 || version > eis::Handshake::VERSION {
+                    return Err(HandshakeError::InvalidHandshakeVersion(version));
+                }
+                self.handshake_version = Some(version);
+            }
             eis::handshake::Request::Name { name } => {
                 if self.name.is_some() {
                     return Err(HandshakeError::DuplicateEvent);
@@ -226,17 +568,12 @@ impl EisHandshaker {
                 self.context_type = Some(context_type);
             }
             eis::handshake::Request::InterfaceVersion { name, version } => {
-                if let Some((interface, server_version)) = interfaces().get_key_value(name.as_str())
-                {
+                if let Some(server_version) = self.interfaces.version(&name) {
                     self.negotiated_interfaces
-                        .insert((*interface).to_owned(), version.min(*server_version));
+                        .insert(name, version.min(server_version));
                 }
             }
             eis::handshake::Request::Finish => {
-                for (interface, version) in &self.negotiated_interfaces {
-                    handshake.interface_version(interface, *version);
-                }
-
                 if !self.negotiated_interfaces.contains_key("ei_connection")
                     || !self.negotiated_interfaces.contains_key("ei_pingpong")
                     || !self.negotiated_interfaces.contains_key("ei_callback")
@@ -244,19 +581,18 @@ impl EisHandshaker {
                     return Err(HandshakeError::MissingInterface);
                 }
 
-                let connection = handshake.connection(self.initial_serial, 1);
-
-                // Protocol spec says `context_type` is optional, defaults to receiver
-                let context_type = self
-                    .context_type
-                    .unwrap_or(ei::handshake::ContextType::Receiver);
-
-                return Ok(Some(EisHandshakeResp {
-                    connection,
-                    name: self.name.clone(),
-                    context_type,
-                    negotiated_interfaces: mem::take(&mut self.negotiated_interfaces),
-                }));
+                let context_type = self.context_type();
+                let authorization = if let Some(authorizer) = &mut self.authorizer {
+                    authorizer(&ClientInfo {
+                        name: self.name.as_deref(),
+                        context_type,
+                        negotiated_interfaces: &self.negotiated_interfaces,
+                        peer_credentials: self.context.peer_credentials(),
+                    })
+                } else {
+                    Authorization::Accept
+                };
+                return self.apply_authorization(&handshake, authorization);
             }
         }
         Ok(None)
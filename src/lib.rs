@@ -5,14 +5,20 @@
 //! This library is currently **incomplete** and subject to change. It should probably do more to provide a more high level API that handles the things a client/server needs to deal with.
 //!
 //! Setting the env var `REIS_DEBUG` will make the library print ei messages it sends and receives.
+//! To process them some other way, use a [message observer](observer) or the `tracing` feature.
 //!
 //! # Features
 //!
 //! `reis` has the following Cargo features:
 //!
-//! - `tokio`: Enables tokio support for clients.
-//! - `calloop`: Enables calloop sources for EIS implementations. Somewhat experimental and
-//!   incomplete.
+//! - `tokio`: Enables tokio support for clients and EIS implementations.
+//! - `async-io`: Enables async-io support for clients and EIS implementations.
+//! - `calloop`: Enables calloop sources for clients and EIS implementations. Somewhat
+//!   experimental and incomplete.
+//! - `tracing`: Emits each message sent or received as a `TRACE` event with target `reis`.
+//!   See also [`observer`].
+//! - `testing`: Enables the `testing` module, for testing clients and EIS implementations
+//!   in-process. With `calloop`, this includes a scriptable mock EIS implementation.
 
 #![forbid(unsafe_code)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -20,6 +26,7 @@
 // TODO split up
 
 pub use wire::PendingRequestResult; // XXX types? names?
+pub mod capture;
 pub mod ei;
 mod eiproto_ei;
 mod eiproto_eis;
@@ -30,13 +37,17 @@ pub use error::Error;
 pub mod event; // XXX reorganize?
 pub mod handshake; // XXX ^
 mod object;
+pub mod observer;
 pub mod request;
 pub use object::Object;
+mod transport;
+pub use transport::Transport;
 mod util;
 mod wire;
 
 pub use enumflags2;
 
+pub use wire::Arg;
 pub use wire::Interface;
 pub use wire::ParseError;
 
@@ -47,6 +58,8 @@ mod async_shared;
 #[cfg(feature = "calloop")]
 //#[doc(hidden)] // TODO
 pub mod calloop;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
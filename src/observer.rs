@@ -0,0 +1,99 @@
+//! Observing protocol messages sent and received by a context.
+//!
+//! Register a [`MessageObserver`] with [`ei::Context::set_message_observer`] or
+//! [`eis::Context::set_message_observer`] to receive every decoded message. With the `tracing`
+//! feature, each message is also emitted as a `TRACE` event with target `reis`.
+//!
+//! [`ei::Context::set_message_observer`]: crate::ei::Context::set_message_observer
+//! [`eis::Context::set_message_observer`]: crate::eis::Context::set_message_observer
+
+use std::{collections::HashSet, fmt};
+
+use crate::Arg;
+
+/// Whether a message was sent or received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Message received from the peer.
+    Incoming,
+    /// Message sent to the peer.
+    Outgoing,
+}
+
+/// A decoded protocol message, passed to a [`MessageObserver`].
+#[derive(Debug)]
+pub struct Message<'a> {
+    /// Whether the message was sent or received.
+    pub direction: Direction,
+    /// ID of the object the message is for.
+    pub object_id: u64,
+    /// Interface of the object, like `ei_device`, or `UNKNOWN`.
+    pub interface: &'a str,
+    /// Opcode of the message.
+    pub opcode: u32,
+    /// Name of the request or event, like `frame`, or `UNKNOWN`.
+    pub name: &'a str,
+    /// Arguments of the message. Arguments giving the interface and version of a new object
+    /// are omitted.
+    pub args: &'a [Arg<'a>],
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.direction == Direction::Incoming {
+            write!(f, " -> ")?;
+        }
+        write!(f, "{}@{:x}.{}(", self.interface, self.object_id, self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Callback receiving the messages of a context.
+pub struct MessageObserver {
+    callback: Box<dyn Fn(&Message<'_>) + Send + Sync>,
+    interfaces: Option<HashSet<String>>,
+}
+
+impl fmt::Debug for MessageObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MessageObserver")
+            .field("interfaces", &self.interfaces)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MessageObserver {
+    /// Creates an observer calling `callback` for each message.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&Message<'_>) + Send + Sync + 'static,
+    {
+        Self {
+            callback: Box::new(callback),
+            interfaces: None,
+        }
+    }
+
+    /// Only observes messages for objects with one of the given interfaces, like `ei_device`.
+    #[must_use]
+    pub fn with_interfaces<'a>(mut self, interfaces: impl IntoIterator<Item = &'a str>) -> Self {
+        self.interfaces = Some(interfaces.into_iter().map(str::to_owned).collect());
+        self
+    }
+
+    pub(crate) fn observes(&self, interface: &str) -> bool {
+        self.interfaces
+            .as_ref()
+            .map_or(true, |interfaces| interfaces.contains(interface))
+    }
+
+    pub(crate) fn notify(&self, message: &Message<'_>) {
+        (self.callback)(message);
+    }
+}
@@ -13,13 +13,13 @@ use crate::{
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fmt,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc, Mutex, Weak,
-    },
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker},
 };
 
-pub use crate::event::DeviceCapability;
+pub use crate::event::{DeviceCapability, Region};
 
 // For compatability, defined the same way as libei
 const EIS_MAX_TOUCHES: usize = 16;
@@ -39,6 +39,17 @@ pub enum RequestError {
     TooManyTouches,
     /// Empty or too long text in `ei_text.utf8`
     InvalidTextLength,
+    /// Touch motion, up or cancel event for a touch ID that is not down
+    UnknownTouchId,
+    /// Touch coordinates outside of every region of the device
+    TouchOutsideRegion,
+    /// Absolute pointer coordinates outside of every region of the device
+    PointerOutsideRegion,
+    /// Absolute pointer or touch coordinates that are infinite or NaN
+    InvalidCoordinates,
+    /// Capabilities in `ei_seat.bind` or `ei_seat.request_device` not permitted by the
+    /// [capability policy](Connection::set_capability_policy) of the connection.
+    CapabilityNotPermitted,
 }
 impl fmt::Display for RequestError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -47,10 +58,35 @@ impl fmt::Display for RequestError {
             Self::DuplicatedTouchDown => write!(f, "Touch down event for duplicated touch ID"),
             Self::TooManyTouches => write!(f, "Too many simultaneous touch events"),
             Self::InvalidTextLength => write!(f, "Empty or too long text string"),
+            Self::UnknownTouchId => write!(f, "Touch event for touch ID that is not down"),
+            Self::TouchOutsideRegion => write!(f, "Touch coordinates outside of device regions"),
+            Self::PointerOutsideRegion => {
+                write!(f, "Absolute pointer coordinates outside of device regions")
+            }
+            Self::InvalidCoordinates => {
+                write!(f, "Infinite or NaN absolute pointer or touch coordinates")
+            }
+            Self::CapabilityNotPermitted => write!(f, "Capabilities not permitted for client"),
         }
     }
 }
 
+/// How [`EisRequestConverter`] handles absolute pointer motion outside of every region
+/// advertised for the device.
+///
+/// Set with [`Connection::set_out_of_bounds_policy`]. Devices without regions accept any
+/// coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutOfBoundsPolicy {
+    /// Moves the coordinates to the closest point within the nearest region.
+    Clamp,
+    /// Silently discards the motion.
+    #[default]
+    Drop,
+    /// Treats the motion as a protocol error, with [`RequestError::PointerOutsideRegion`].
+    Error,
+}
+
 #[derive(Debug)]
 struct ConnectionInner {
     context: eis::Context,
@@ -59,7 +95,11 @@ struct ConnectionInner {
     devices: Mutex<HashMap<eis::Device, Device>>,
     device_for_interface: Mutex<HashMap<Object, Device>>,
     last_serial: Mutex<u32>,
-    disconnected: AtomicBool,
+    // Reason sent in `ei_connection.disconnected`
+    disconnected: Mutex<Option<DisconnectReason>>,
+    out_of_bounds_policy: Mutex<OutOfBoundsPolicy>,
+    capability_policy: Mutex<BitFlags<DeviceCapability>>,
+    pings: Mutex<HashMap<eis::Pingpong, Ping>>,
 }
 
 /// High-level server-side wrapper for `ei_connection`.
@@ -103,15 +143,87 @@ impl Connection {
         // If flush fails because buffer is full, client can just get an EOF without
         // a message.
         let _ = self.flush();
-        self.0.disconnected.store(true, Ordering::SeqCst);
+        *self.0.disconnected.lock().unwrap() = Some(reason);
+        self.abandon_pings();
         // Shutdown read end of socket, so anything reading/polling it will get EOF,
         // without waiting for client to disconnect first.
         self.0.context.0.shutdown_read();
     }
 
-    #[cfg(feature = "calloop")]
+    /// Sends `ei_connection.ping`, to check that the client is still responsive.
+    ///
+    /// The returned [`Ping`] is done once the client answers with `ei_pingpong.done`. Call
+    /// [`flush`](Self::flush) afterwards to actually send the ping.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    #[must_use]
+    pub fn ping(&self) -> Ping {
+        let version = self.interface_version(eis::Pingpong::NAME).unwrap_or(1);
+        let pingpong = self.connection().ping(version);
+        let ping = Ping(Arc::new(Mutex::new(PingInner {
+            state: PingState::Pending,
+            waker: None,
+        })));
+        self.0.pings.lock().unwrap().insert(pingpong, ping.clone());
+        ping
+    }
+
+    fn abandon_pings(&self) {
+        for (_, ping) in self.0.pings.lock().unwrap().drain() {
+            ping.finish(PingState::Abandoned);
+        }
+    }
+
+    #[cfg(any(feature = "tokio", feature = "async-io"))]
     pub(crate) fn has_sent_disconnected(&self) -> bool {
-        self.0.disconnected.load(Ordering::SeqCst)
+        self.sent_disconnect_reason().is_some()
+    }
+
+    #[cfg(any(feature = "calloop", feature = "tokio", feature = "async-io"))]
+    pub(crate) fn sent_disconnect_reason(&self) -> Option<DisconnectReason> {
+        *self.0.disconnected.lock().unwrap()
+    }
+
+    // Sends a protocol error to the client, before the connection is dropped.
+    #[cfg(any(
+        feature = "calloop",
+        feature = "tokio",
+        feature = "async-io",
+        feature = "testing"
+    ))]
+    pub(crate) fn disconnect_with_error(&self, err: &Error) -> DisconnectReason {
+        let reason = if let Error::Request(
+            RequestError::InvalidCapabilities | RequestError::CapabilityNotPermitted,
+        ) = err
+        {
+            DisconnectReason::Value
+        } else {
+            DisconnectReason::Protocol
+        };
+        self.disconnected(reason, Some(&err.to_string()));
+        let _ = self.flush();
+        reason
+    }
+
+    // Without `ei_seat` and `ei_device`, no devices can be offered to the client.
+    #[cfg(any(
+        feature = "calloop",
+        feature = "tokio",
+        feature = "async-io",
+        feature = "testing"
+    ))]
+    pub(crate) fn check_required_interfaces(&self) -> Result<(), Error> {
+        if !self.has_interface("ei_seat") || !self.has_interface("ei_device") {
+            self.disconnected(
+                DisconnectReason::Protocol,
+                Some("Need `ei_seat` and `ei_device`"),
+            );
+            let _ = self.flush();
+            return Err(crate::handshake::HandshakeError::MissingInterface.into());
+        }
+        Ok(())
     }
 
     /// Sends buffered messages. Call after you're finished with sending events.
@@ -138,6 +250,14 @@ impl Connection {
         self.0.handshake_resp.name.as_deref()
     }
 
+    /// Returns the credentials of the client process.
+    ///
+    /// See [`eis::Context::peer_credentials`].
+    #[must_use]
+    pub fn peer_credentials(&self) -> Option<&eis::PeerCredentials> {
+        self.0.context.peer_credentials()
+    }
+
     // Use type instead of string?
     /// Returns `true` if the connection has negotiated support for the named interface.
     #[must_use]
@@ -184,6 +304,56 @@ impl Connection {
         res
     }
 
+    /// Returns how absolute pointer motion outside of the device's regions is handled.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    #[must_use]
+    pub fn out_of_bounds_policy(&self) -> OutOfBoundsPolicy {
+        *self.0.out_of_bounds_policy.lock().unwrap()
+    }
+
+    /// Sets how absolute pointer motion outside of the device's regions is handled.
+    ///
+    /// Defaults to [`OutOfBoundsPolicy::Drop`].
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    pub fn set_out_of_bounds_policy(&self, policy: OutOfBoundsPolicy) {
+        *self.0.out_of_bounds_policy.lock().unwrap() = policy;
+    }
+
+    /// Returns the capabilities this client is permitted to use.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    #[must_use]
+    pub fn capability_policy(&self) -> BitFlags<DeviceCapability> {
+        *self.0.capability_policy.lock().unwrap()
+    }
+
+    /// Restricts the capabilities this client is permitted to use.
+    ///
+    /// Seats added with [`Connection::add_seat`] only advertise, and devices added with
+    /// [`Seat::add_device`] only get interfaces for, capabilities within the policy. A client
+    /// binding or requesting a device with other capabilities is a protocol error,
+    /// [`RequestError::CapabilityNotPermitted`]. Devices already added are not affected.
+    ///
+    /// To confine absolute pointer motion to some area, combine this with
+    /// [`Device::add_region`] and [`Connection::set_out_of_bounds_policy`].
+    ///
+    /// Defaults to all capabilities.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    pub fn set_capability_policy(&self, capabilities: BitFlags<DeviceCapability>) {
+        *self.0.capability_policy.lock().unwrap() = capabilities;
+    }
+
     fn device_for_interface<T: DeviceInterface>(&mut self, interface: &T) -> Option<Device> {
         self.0
             .device_for_interface
@@ -195,6 +365,10 @@ impl Connection {
 
     /// Adds a seat to the connection.
     ///
+    /// Capabilities whose interfaces were not negotiated in the handshake, or that are not
+    /// permitted by the [capability policy](Connection::set_capability_policy), are not
+    /// advertised.
+    ///
     /// # Panics
     ///
     /// Will panic if an internal Mutex is poisoned.
@@ -206,16 +380,14 @@ impl Connection {
             seat.name(name);
         }
 
+        let capabilities = (capabilities & self.capability_policy())
+            .iter()
+            // Not negotiated
+            .filter(|capability| self.has_interface(capability.interface_name()))
+            .collect::<BitFlags<_>>();
         for capability in capabilities {
-            let interface_name = capability.interface_name();
-
-            if !self.has_interface(interface_name) {
-                // Not negotiated
-                continue;
-            }
-
             // Using bitflag value because as the server we control its meaning
-            seat.capability(capability as u64, interface_name);
+            seat.capability(capability as u64, capability.interface_name());
         }
 
         seat.done();
@@ -250,6 +422,8 @@ impl Drop for EisRequestConverter {
         for device in self.connection.0.devices.lock().unwrap().values() {
             device.0.pending_requests.lock().unwrap().clear();
         }
+        // Nothing will handle `ei_pingpong.done` anymore
+        self.connection.abandon_pings();
     }
 }
 
@@ -270,7 +444,10 @@ impl EisRequestConverter {
                 devices: Mutex::default(),
                 device_for_interface: Mutex::default(),
                 last_serial: Mutex::new(initial_serial),
-                disconnected: AtomicBool::new(false),
+                disconnected: Mutex::new(None),
+                out_of_bounds_policy: Mutex::default(),
+                capability_policy: Mutex::new(BitFlags::all()),
+                pings: Mutex::default(),
             })),
         }
     }
@@ -347,9 +524,12 @@ impl EisRequestConverter {
                 self.handle_connection_request(request)?;
             }
             eis::Request::Callback(_callback, request) => match request {},
-            eis::Request::Pingpong(_ping_pong, request) => match request {
+            eis::Request::Pingpong(ping_pong, request) => match request {
                 eis::pingpong::Request::Done { callback_data: _ } => {
-                    // TODO
+                    let ping = self.connection.0.pings.lock().unwrap().remove(&ping_pong);
+                    if let Some(ping) = ping {
+                        ping.finish(PingState::Done);
+                    }
                 }
             },
             eis::Request::Seat(seat, request) => self.handle_seat_request(&seat, &request)?,
@@ -361,7 +541,7 @@ impl EisRequestConverter {
                 self.handle_pointer_request(pointer, request);
             }
             eis::Request::PointerAbsolute(pointer_absolute, request) => {
-                self.handle_pointer_absolute_request(pointer_absolute, request);
+                self.handle_pointer_absolute_request(pointer_absolute, request)?;
             }
             eis::Request::Scroll(scroll, request) => {
                 self.handle_scroll_request(scroll, request);
@@ -422,6 +602,9 @@ impl EisRequestConverter {
 
                 let capabilities = DeviceCapability::from_bits(*capabilities)
                     .map_err(|_err| RequestError::InvalidCapabilities)?;
+                if !self.connection.capability_policy().contains(capabilities) {
+                    return Err(RequestError::CapabilityNotPermitted.into());
+                }
                 if !seat.0.advertised_capabilities.contains(capabilities) {
                     return Err(RequestError::InvalidCapabilities.into());
                 }
@@ -436,6 +619,9 @@ impl EisRequestConverter {
 
                 let capabilities = DeviceCapability::from_bits(*capabilities)
                     .map_err(|_err| RequestError::InvalidCapabilities)?;
+                if !self.connection.capability_policy().contains(capabilities) {
+                    return Err(RequestError::CapabilityNotPermitted.into());
+                }
                 if !seat.0.advertised_capabilities.contains(capabilities) {
                     return Err(RequestError::InvalidCapabilities.into());
                 }
@@ -470,6 +656,8 @@ impl EisRequestConverter {
                 last_serial,
                 sequence,
             } => {
+                // The client has seen `ei_device.paused` before the device was resumed
+                device.0.paused_touch_ids.lock().unwrap().clear();
                 self.queue_request(EisRequest::DeviceStartEmulating(DeviceStartEmulating {
                     device,
                     last_serial,
@@ -561,9 +749,9 @@ impl EisRequestConverter {
         &mut self,
         pointer_absolute: eis::PointerAbsolute,
         request: eis::pointer_absolute::Request,
-    ) {
+    ) -> Result<(), Error> {
         let Some(device) = self.connection.device_for_interface(&pointer_absolute) else {
-            return;
+            return Ok(());
         };
         match request {
             eis::pointer_absolute::Request::Release => {
@@ -577,14 +765,23 @@ impl EisRequestConverter {
                     .with_next_serial(|serial| pointer_absolute.destroyed(serial));
             }
             eis::pointer_absolute::Request::MotionAbsolute { x, y } => {
+                if !x.is_finite() || !y.is_finite() {
+                    return Err(RequestError::InvalidCoordinates.into());
+                }
+                let policy = self.connection.out_of_bounds_policy();
+                let Some((region, x, y)) = device.locate_pointer(x, y, policy)? else {
+                    return Ok(());
+                };
                 self.queue_request(EisRequest::PointerMotionAbsolute(PointerMotionAbsolute {
                     device,
                     dx_absolute: x,
                     dy_absolute: y,
+                    region,
                     time: 0,
                 }));
             }
         }
+        Ok(())
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -691,10 +888,18 @@ impl EisRequestConverter {
                 if down_touch_ids.len() == EIS_MAX_TOUCHES {
                     return Err(RequestError::TooManyTouches.into());
                 }
-                if !down_touch_ids.insert(touchid) {
+                if down_touch_ids.contains(&touchid) {
                     return Err(RequestError::DuplicatedTouchDown.into());
                 }
+                if !x.is_finite() || !y.is_finite() {
+                    return Err(RequestError::InvalidCoordinates.into());
+                }
+                if !device.in_regions(x, y) {
+                    return Err(RequestError::TouchOutsideRegion.into());
+                }
+                down_touch_ids.insert(touchid);
                 drop(down_touch_ids);
+                device.0.paused_touch_ids.lock().unwrap().remove(&touchid);
                 self.queue_request(EisRequest::TouchDown(TouchDown {
                     device,
                     touch_id: touchid,
@@ -704,24 +909,32 @@ impl EisRequestConverter {
                 }));
             }
             eis::touchscreen::Request::Motion { touchid, x, y } => {
-                if device.0.down_touch_ids.lock().unwrap().contains(&touchid) {
-                    self.queue_request(EisRequest::TouchMotion(TouchMotion {
-                        device,
-                        touch_id: touchid,
-                        x,
-                        y,
-                        time: 0,
-                    }));
+                if !device.0.down_touch_ids.lock().unwrap().contains(&touchid) {
+                    return device.check_paused_touch_id(touchid, false);
+                }
+                if !x.is_finite() || !y.is_finite() {
+                    return Err(RequestError::InvalidCoordinates.into());
+                }
+                if !device.in_regions(x, y) {
+                    return Err(RequestError::TouchOutsideRegion.into());
                 }
+                self.queue_request(EisRequest::TouchMotion(TouchMotion {
+                    device,
+                    touch_id: touchid,
+                    x,
+                    y,
+                    time: 0,
+                }));
             }
             eis::touchscreen::Request::Up { touchid } => {
-                if device.0.down_touch_ids.lock().unwrap().remove(&touchid) {
-                    self.queue_request(EisRequest::TouchUp(TouchUp {
-                        device,
-                        touch_id: touchid,
-                        time: 0,
-                    }));
+                if !device.0.down_touch_ids.lock().unwrap().remove(&touchid) {
+                    return device.check_paused_touch_id(touchid, true);
                 }
+                self.queue_request(EisRequest::TouchUp(TouchUp {
+                    device,
+                    touch_id: touchid,
+                    time: 0,
+                }));
             }
             eis::touchscreen::Request::Cancel { touchid } => {
                 if touchscreen.version() < 2 {
@@ -730,13 +943,14 @@ impl EisRequestConverter {
                         touchscreen.version(),
                     ));
                 }
-                if device.0.down_touch_ids.lock().unwrap().remove(&touchid) {
-                    self.queue_request(EisRequest::TouchCancel(TouchCancel {
-                        device,
-                        touch_id: touchid,
-                        time: 0,
-                    }));
+                if !device.0.down_touch_ids.lock().unwrap().remove(&touchid) {
+                    return device.check_paused_touch_id(touchid, true);
                 }
+                self.queue_request(EisRequest::TouchCancel(TouchCancel {
+                    device,
+                    touch_id: touchid,
+                    time: 0,
+                }));
             }
         }
         Ok(())
@@ -785,6 +999,97 @@ impl EisRequestConverter {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PingState {
+    Pending,
+    Done,
+    Abandoned,
+}
+
+#[derive(Debug)]
+struct PingInner {
+    state: PingState,
+    waker: Option<Waker>,
+}
+
+/// A ping sent with [`Connection::ping`].
+///
+/// Can be polled with [`is_done`](Self::is_done), or awaited. The future resolves to `true` once
+/// the client has answered, or to `false` if the connection was closed first.
+#[derive(Clone, Debug)]
+pub struct Ping(Arc<Mutex<PingInner>>);
+
+impl Ping {
+    /// Returns `true` if the client has answered the ping.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.0.lock().unwrap().state == PingState::Done
+    }
+
+    fn finish(&self, state: PingState) {
+        let mut inner = self.0.lock().unwrap();
+        inner.state = state;
+        if let Some(waker) = inner.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for Ping {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let mut inner = self.0.lock().unwrap();
+        match inner.state {
+            PingState::Pending => {
+                inner.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            PingState::Done => Poll::Ready(true),
+            PingState::Abandoned => Poll::Ready(false),
+        }
+    }
+}
+
+impl PartialEq for Ping {
+    fn eq(&self, rhs: &Ping) -> bool {
+        Arc::ptr_eq(&self.0, &rhs.0)
+    }
+}
+
+impl Eq for Ping {}
+
+// Returns the region nearest to the point, and the point moved inside of it.
+fn clamp_to_regions(regions: &[Region], x: f32, y: f32) -> (&Region, f32, f32) {
+    // Upper bounds are exclusive, so clamp to the float just below them
+    fn clamp(value: f32, offset: u32, size: u32) -> f32 {
+        let min = f64::from(offset) as f32;
+        let max = (f64::from(offset) + f64::from(size)) as f32;
+        let max = if max > min {
+            f32::from_bits(max.to_bits() - 1)
+        } else {
+            min
+        };
+        value.clamp(min, max)
+    }
+
+    regions
+        .iter()
+        .map(|region| {
+            let clamped_x = clamp(x, region.x, region.width);
+            let clamped_y = clamp(y, region.y, region.height);
+            let distance = (clamped_x - x).powi(2) + (clamped_y - y).powi(2);
+            (region, clamped_x, clamped_y, distance)
+        })
+        .min_by(|a, b| a.3.total_cmp(&b.3))
+        .map(|(region, x, y, _)| (region, x, y))
+        .expect("no regions to clamp to")
+}
+
 struct SeatInner {
     seat: eis::Seat,
     name: Option<String>,
@@ -818,8 +1123,9 @@ impl Seat {
     // builder pattern?
     /// Adds a device to the connection.
     ///
-    /// Capabilities that were not advertised on the seat will be ignored. An interface
-    /// will be created for all capabilities that do exist on the seat.
+    /// Capabilities that were not advertised on the seat, or that are not permitted by the
+    /// [capability policy](Connection::set_capability_policy), will be ignored. An interface
+    /// will be created for all other capabilities.
     ///
     /// # Panics
     ///
@@ -845,7 +1151,10 @@ impl Seat {
         device.device_type(device_type);
         // TODO
         // dimensions
-        // regions; region_mapping_id
+        let capabilities = match &connection {
+            Some(connection) => capabilities & connection.capability_policy(),
+            None => capabilities,
+        };
         let mut interfaces = HashMap::new();
         for capability in capabilities {
             if !self.0.advertised_capabilities.contains(capability) {
@@ -882,6 +1191,8 @@ impl Seat {
             interfaces: Mutex::new(interfaces),
             handle: self.0.handle.clone(),
             down_touch_ids: Mutex::new(HashSet::new()),
+            paused_touch_ids: Mutex::new(HashSet::new()),
+            regions: Mutex::new(Vec::new()),
             pending_requests: Mutex::new(VecDeque::new()),
         }));
         if let Some(handle) = connection {
@@ -1002,6 +1313,11 @@ struct DeviceInner {
     handle: Weak<ConnectionInner>,
     // Applicable only for touch devices
     down_touch_ids: Mutex<HashSet<u32>>,
+    // Touches that were down when the device was paused. The client may still send
+    // motion/up/cancel for them until it has seen `ei_device.paused`, so they are forgotten
+    // once it starts emulating again.
+    paused_touch_ids: Mutex<HashSet<u32>>,
+    regions: Mutex<Vec<Region>>,
     // Requests received for this device but not yet committed by an `ei_device.frame`.
     pending_requests: Mutex<VecDeque<EisRequest>>,
 }
@@ -1073,6 +1389,91 @@ impl Device {
             .contains_key(capability.interface_name())
     }
 
+    /// Advertises a region of the device to the client.
+    ///
+    /// Must only be called before the device is done, i.e. in the `before_done_cb` passed to
+    /// [`Seat::add_device`]. The region mapping ID is only sent if the client supports version 2
+    /// of `ei_device`.
+    ///
+    /// Touch and absolute pointer coordinates sent by the client are validated against the
+    /// advertised regions.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    pub fn add_region(&self, region: Region) {
+        if let Some(mapping_id) = &region.mapping_id {
+            if self.device().version() >= 2 {
+                self.device().region_mapping_id(mapping_id);
+            }
+        }
+        self.device().region(
+            region.x,
+            region.y,
+            region.width,
+            region.height,
+            region.scale,
+        );
+        self.0.regions.lock().unwrap().push(region);
+    }
+
+    /// Returns the regions advertised with [`add_region`](Self::add_region).
+    ///
+    /// # Panics
+    ///
+    /// Will panic if an internal Mutex is poisoned.
+    #[must_use]
+    pub fn regions(&self) -> Vec<Region> {
+        self.0.regions.lock().unwrap().clone()
+    }
+
+    // Finds the region containing an absolute pointer position, applying `policy` if it is
+    // outside of every region. Returns `None` if the motion is dropped.
+    fn locate_pointer(
+        &self,
+        x: f32,
+        y: f32,
+        policy: OutOfBoundsPolicy,
+    ) -> Result<Option<(Option<Region>, f32, f32)>, Error> {
+        let regions = self.0.regions.lock().unwrap();
+        if regions.is_empty() {
+            return Ok(Some((None, x, y)));
+        }
+        if let Some(region) = regions.iter().find(|region| region.contains(x, y)) {
+            return Ok(Some((Some(region.clone()), x, y)));
+        }
+        match policy {
+            OutOfBoundsPolicy::Clamp => {
+                let (region, x, y) = clamp_to_regions(&regions, x, y);
+                Ok(Some((Some(region.clone()), x, y)))
+            }
+            OutOfBoundsPolicy::Drop => Ok(None),
+            OutOfBoundsPolicy::Error => Err(RequestError::PointerOutsideRegion.into()),
+        }
+    }
+
+    // Devices without regions accept any coordinates
+    fn in_regions(&self, x: f32, y: f32) -> bool {
+        let regions = self.0.regions.lock().unwrap();
+        regions.is_empty() || regions.iter().any(|region| region.contains(x, y))
+    }
+
+    // Touch events for a touch ID that isn't down are only accepted if the touch was ended
+    // by pausing the device.
+    fn check_paused_touch_id(&self, touch_id: u32, end: bool) -> Result<(), Error> {
+        let mut paused_touch_ids = self.0.paused_touch_ids.lock().unwrap();
+        let known = if end {
+            paused_touch_ids.remove(&touch_id)
+        } else {
+            paused_touch_ids.contains(&touch_id)
+        };
+        if known {
+            Ok(())
+        } else {
+            Err(RequestError::UnknownTouchId.into())
+        }
+    }
+
     /// Removes this device and associated interfaces from the connection.
     ///
     /// After removal, [`interface`](Self::interface) returns `None` and
@@ -1130,7 +1531,12 @@ impl Device {
         if let Some(handle) = self.0.handle.upgrade().map(Connection) {
             handle.with_next_serial(|serial| self.device().paused(serial));
         }
-        self.0.down_touch_ids.lock().unwrap().clear();
+        let down_touch_ids = std::mem::take(&mut *self.0.down_touch_ids.lock().unwrap());
+        self.0
+            .paused_touch_ids
+            .lock()
+            .unwrap()
+            .extend(down_touch_ids);
     }
 
     // TODO: statically restrict the below to receiver context?
@@ -1355,6 +1761,7 @@ pub struct PointerMotion {
 
 /// High-level translation of [`ei_pointer_absolute.motion_absolute`](eis::pointer_absolute::Request::MotionAbsolute).
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub struct PointerMotionAbsolute {
     /// High-level [`Device`] wrapper.
     pub device: Device,
@@ -1364,6 +1771,10 @@ pub struct PointerMotionAbsolute {
     pub dx_absolute: f32,
     /// Absolute position on the Y axis.
     pub dy_absolute: f32,
+    /// The advertised region containing the position, if the device has regions.
+    ///
+    /// Its [`mapping_id`](Region::mapping_id) can be used to find the matching output.
+    pub region: Option<Region>,
 }
 
 /// High-level translation of [`ei_button.button`](eis::button::Request::Button).
@@ -0,0 +1,558 @@
+//! Mock EIS implementation that follows a script, for testing clients.
+//!
+//! A [`MockServer`] runs an [`EisRequestSource`] on its own calloop event loop. Once a client
+//! connects, it advertises the seats it was given with [`MockServer::with_seat`]. When the
+//! client binds capabilities of a seat, it adds the devices of that seat with any of those
+//! capabilities, along with their regions and keymaps, and resumes them.
+//!
+//! Every [`EisRequest`] the client sends is recorded, and the input sent to each device can
+//! be checked with [`MockServer::assert_input`]:
+//!
+//! ```
+//! use std::{os::unix::net::UnixStream, time::Duration};
+//! use reis::{
+//!     eis,
+//!     request::DeviceCapability,
+//!     testing::mock::{MockDevice, MockSeat, MockServer},
+//! };
+//!
+//! let (client, server) = UnixStream::pair()?;
+//! let mut mock = MockServer::new(eis::Context::new(server)?)?.with_seat(
+//!     MockSeat::new("seat", DeviceCapability::Pointer.into()).with_device(MockDevice::new(
+//!         "pointer",
+//!         eis::device::DeviceType::Virtual,
+//!         DeviceCapability::Pointer.into(),
+//!     )),
+//! );
+//!
+//! // Drive a client connected to `client`, dispatching the server in between
+//! # drop(client);
+//! mock.dispatch(Duration::ZERO)?;
+//! mock.assert_input("pointer", &[]);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::unix::io::{AsFd, OwnedFd},
+    time::Duration,
+};
+
+use enumflags2::BitFlags;
+
+use crate::{
+    calloop::{DisconnectInitiator, EisRequestSource, EisRequestSourceEvent},
+    eis::{self, connection::DisconnectReason, device::DeviceType, keyboard::KeymapType},
+    request::{self, Connection, DeviceCapability, EisRequest, Region},
+    Error,
+};
+
+/// Seat advertised by a [`MockServer`].
+#[derive(Debug)]
+pub struct MockSeat {
+    name: String,
+    capabilities: BitFlags<DeviceCapability>,
+    devices: Vec<MockDevice>,
+    seat: Option<request::Seat>,
+}
+
+impl MockSeat {
+    /// Creates a seat with the given capabilities, and no devices.
+    #[must_use]
+    pub fn new(name: &str, capabilities: BitFlags<DeviceCapability>) -> Self {
+        Self {
+            name: name.to_owned(),
+            capabilities,
+            devices: Vec::new(),
+            seat: None,
+        }
+    }
+
+    /// Adds a device to the seat, once the client binds any of its capabilities.
+    #[must_use]
+    pub fn with_device(mut self, device: MockDevice) -> Self {
+        self.devices.push(device);
+        self
+    }
+}
+
+/// Device added by a [`MockServer`].
+#[derive(Debug)]
+pub struct MockDevice {
+    name: String,
+    device_type: DeviceType,
+    capabilities: BitFlags<DeviceCapability>,
+    regions: Vec<Region>,
+    keymap: Option<(KeymapType, OwnedFd, u32)>,
+    paused: bool,
+    device: Option<request::Device>,
+}
+
+impl MockDevice {
+    /// Creates a device with the given capabilities, that is resumed once added.
+    #[must_use]
+    pub fn new(
+        name: &str,
+        device_type: DeviceType,
+        capabilities: BitFlags<DeviceCapability>,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            device_type,
+            capabilities,
+            regions: Vec::new(),
+            keymap: None,
+            paused: false,
+            device: None,
+        }
+    }
+
+    /// Adds a region to the device.
+    #[must_use]
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.regions.push(region);
+        self
+    }
+
+    /// Sends `keymap` to the client, if the device has a keyboard.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the keymap can't be written to a memfd.
+    pub fn with_keymap(mut self, keymap_type: KeymapType, keymap: &str) -> io::Result<Self> {
+        let fd = rustix::fs::memfd_create("reis-mock-keymap", rustix::fs::MemfdFlags::CLOEXEC)?;
+        let mut file = File::from(fd);
+        file.write_all(keymap.as_bytes())?;
+        let size = u32::try_from(keymap.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "keymap too large"))?;
+        self.keymap = Some((keymap_type, file.into(), size));
+        Ok(self)
+    }
+
+    /// Leaves the device paused once added, until [`MockServer::resume`] is called.
+    #[must_use]
+    pub fn with_paused(mut self) -> Self {
+        self.paused = true;
+        self
+    }
+}
+
+/// Input received by a device of a [`MockServer`], without timestamps.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(missing_docs)] // Fields are those of the corresponding requests
+pub enum Input {
+    /// [`EisRequest::DeviceStartEmulating`].
+    StartEmulating,
+    /// [`EisRequest::DeviceStopEmulating`].
+    StopEmulating,
+    /// [`EisRequest::Frame`].
+    Frame,
+    /// [`EisRequest::PointerMotion`].
+    PointerMotion { dx: f32, dy: f32 },
+    /// [`EisRequest::PointerMotionAbsolute`].
+    PointerMotionAbsolute { x: f32, y: f32 },
+    /// [`EisRequest::Button`].
+    Button {
+        button: u32,
+        state: eis::button::ButtonState,
+    },
+    /// [`EisRequest::ScrollDelta`].
+    ScrollDelta { dx: f32, dy: f32 },
+    /// [`EisRequest::ScrollStop`].
+    ScrollStop { x: bool, y: bool },
+    /// [`EisRequest::ScrollCancel`].
+    ScrollCancel { x: bool, y: bool },
+    /// [`EisRequest::ScrollDiscrete`].
+    ScrollDiscrete { dx: i32, dy: i32 },
+    /// [`EisRequest::KeyboardKey`].
+    KeyboardKey {
+        key: u32,
+        state: eis::keyboard::KeyState,
+    },
+    /// [`EisRequest::TouchDown`].
+    TouchDown { touch_id: u32, x: f32, y: f32 },
+    /// [`EisRequest::TouchMotion`].
+    TouchMotion { touch_id: u32, x: f32, y: f32 },
+    /// [`EisRequest::TouchUp`].
+    TouchUp { touch_id: u32 },
+    /// [`EisRequest::TouchCancel`].
+    TouchCancel { touch_id: u32 },
+    /// [`EisRequest::TextKeysym`].
+    TextKeysym {
+        keysym: u32,
+        state: eis::keyboard::KeyState,
+    },
+    /// [`EisRequest::TextUtf8`].
+    TextUtf8 { text: String },
+}
+
+impl Input {
+    fn from_request(request: &EisRequest) -> Option<Self> {
+        Some(match request {
+            EisRequest::DeviceStartEmulating(_) => Self::StartEmulating,
+            EisRequest::DeviceStopEmulating(_) => Self::StopEmulating,
+            EisRequest::Frame(_) => Self::Frame,
+            EisRequest::PointerMotion(evt) => Self::PointerMotion {
+                dx: evt.dx,
+                dy: evt.dy,
+            },
+            EisRequest::PointerMotionAbsolute(evt) => Self::PointerMotionAbsolute {
+                x: evt.dx_absolute,
+                y: evt.dy_absolute,
+            },
+            EisRequest::Button(evt) => Self::Button {
+                button: evt.button,
+                state: evt.state,
+            },
+            EisRequest::ScrollDelta(evt) => Self::ScrollDelta {
+                dx: evt.dx,
+                dy: evt.dy,
+            },
+            EisRequest::ScrollStop(evt) => Self::ScrollStop { x: evt.x, y: evt.y },
+            EisRequest::ScrollCancel(evt) => Self::ScrollCancel { x: evt.x, y: evt.y },
+            EisRequest::ScrollDiscrete(evt) => Self::ScrollDiscrete {
+                dx: evt.discrete_dx,
+                dy: evt.discrete_dy,
+            },
+            EisRequest::KeyboardKey(evt) => Self::KeyboardKey {
+                key: evt.key,
+                state: evt.state,
+            },
+            EisRequest::TouchDown(evt) => Self::TouchDown {
+                touch_id: evt.touch_id,
+                x: evt.x,
+                y: evt.y,
+            },
+            EisRequest::TouchMotion(evt) => Self::TouchMotion {
+                touch_id: evt.touch_id,
+                x: evt.x,
+                y: evt.y,
+            },
+            EisRequest::TouchUp(evt) => Self::TouchUp {
+                touch_id: evt.touch_id,
+            },
+            EisRequest::TouchCancel(evt) => Self::TouchCancel {
+                touch_id: evt.touch_id,
+            },
+            EisRequest::TextKeysym(evt) => Self::TextKeysym {
+                keysym: evt.keysym,
+                state: evt.state,
+            },
+            EisRequest::TextUtf8(evt) => Self::TextUtf8 {
+                text: evt.text.clone(),
+            },
+            EisRequest::Disconnect
+            | EisRequest::Bind(_)
+            | EisRequest::DeviceClosed(_)
+            | EisRequest::RequestDevice(_)
+            | EisRequest::Ready(_) => return None,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    seats: Vec<MockSeat>,
+    connection: Option<Connection>,
+    requests: Vec<EisRequest>,
+    disconnected: Option<(DisconnectInitiator, DisconnectReason)>,
+    error: Option<Error>,
+    sequence: u32,
+}
+
+impl State {
+    fn handle_event(
+        &mut self,
+        event: Result<EisRequestSourceEvent, Error>,
+        connection: &Connection,
+    ) -> calloop::PostAction {
+        match event {
+            Ok(EisRequestSourceEvent::Connected { .. }) => {
+                for seat in &mut self.seats {
+                    seat.seat = Some(connection.add_seat(Some(&seat.name), seat.capabilities));
+                }
+                self.connection = Some(connection.clone());
+            }
+            Ok(EisRequestSourceEvent::Request(request)) => {
+                if let EisRequest::Bind(bind) = &request {
+                    self.bind(connection, &bind.seat, bind.capabilities);
+                }
+                self.requests.push(request);
+            }
+            Ok(EisRequestSourceEvent::Disconnected {
+                initiator,
+                reason,
+                error,
+            }) => {
+                self.disconnected = Some((initiator, reason));
+                self.error = error;
+                return calloop::PostAction::Remove;
+            }
+            Err(err) => {
+                self.error = Some(err);
+                return calloop::PostAction::Remove;
+            }
+        }
+        let _ = connection.flush();
+        calloop::PostAction::Continue
+    }
+
+    // Adds the devices with bound capabilities, and removes the others.
+    fn bind(
+        &mut self,
+        connection: &Connection,
+        seat: &request::Seat,
+        capabilities: BitFlags<DeviceCapability>,
+    ) {
+        let Some(mock_seat) = self
+            .seats
+            .iter_mut()
+            .find(|s| s.seat.as_ref() == Some(seat))
+        else {
+            return;
+        };
+        for mock_device in &mut mock_seat.devices {
+            let bound = mock_device.capabilities.intersects(capabilities);
+            match (&mock_device.device, bound) {
+                (None, true) => {
+                    let device = add_device(seat, mock_device);
+                    if !mock_device.paused {
+                        device.resumed();
+                        if connection.context_type() == eis::handshake::ContextType::Receiver {
+                            self.sequence += 1;
+                            device.start_emulating(self.sequence);
+                        }
+                    }
+                    mock_device.device = Some(device);
+                }
+                (Some(device), false) => {
+                    device.remove();
+                    mock_device.device = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn device(&self, name: &str) -> Option<&request::Device> {
+        self.seats
+            .iter()
+            .flat_map(|seat| &seat.devices)
+            .find(|device| device.name == name)
+            .and_then(|device| device.device.as_ref())
+    }
+}
+
+fn add_device(seat: &request::Seat, mock_device: &MockDevice) -> request::Device {
+    seat.add_device(
+        Some(&mock_device.name),
+        mock_device.device_type,
+        mock_device.capabilities,
+        |device| {
+            for region in &mock_device.regions {
+                device.add_region(region.clone());
+            }
+            if let (Some((keymap_type, fd, size)), Some(keyboard)) =
+                (&mock_device.keymap, device.interface::<eis::Keyboard>())
+            {
+                keyboard.keymap(*keymap_type, *size, fd.as_fd());
+            }
+        },
+    )
+}
+
+/// EIS implementation for a single client, following a script.
+///
+/// Nothing happens until [`dispatch`](Self::dispatch) is called, so a test can drive the client
+/// and the server from the same thread.
+pub struct MockServer {
+    event_loop: calloop::EventLoop<'static, State>,
+    // `None` once the source has been removed by `disconnect`
+    token: Option<calloop::RegistrationToken>,
+    state: State,
+}
+
+impl std::fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("MockServer")
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MockServer {
+    /// Creates a server for the client connected to `context`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the event loop can't be created.
+    pub fn new(context: eis::Context) -> io::Result<Self> {
+        let event_loop = calloop::EventLoop::try_new()?;
+        let token = event_loop
+            .handle()
+            .insert_source(
+                EisRequestSource::new(context, 1),
+                |event, connection, state: &mut State| Ok(state.handle_event(event, connection)),
+            )
+            .map_err(|err| err.error)?;
+        Ok(Self {
+            event_loop,
+            token: Some(token),
+            state: State::default(),
+        })
+    }
+
+    /// Advertises `seat` to the client, once the handshake is done.
+    #[must_use]
+    pub fn with_seat(mut self, seat: MockSeat) -> Self {
+        self.state.seats.push(seat);
+        self
+    }
+
+    /// Handles the messages sent by the client, waiting up to `timeout` for any to arrive.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if polling fails. Errors communicating with the client are available
+    /// from [`error`](Self::error) instead.
+    pub fn dispatch(&mut self, timeout: Duration) -> io::Result<()> {
+        self.event_loop.dispatch(timeout, &mut self.state)?;
+        Ok(())
+    }
+
+    /// Returns the connection to the client, once the handshake is done.
+    #[must_use]
+    pub fn connection(&self) -> Option<&Connection> {
+        self.state.connection.as_ref()
+    }
+
+    /// Returns the device with the given name, if it has been added.
+    #[must_use]
+    pub fn device(&self, name: &str) -> Option<&request::Device> {
+        self.state.device(name)
+    }
+
+    /// Sends `ei_device.resumed` for the device with the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device hasn't been added.
+    #[track_caller]
+    pub fn resume(&self, device: &str) {
+        self.expect_device(device).resumed();
+        self.flush();
+    }
+
+    /// Sends `ei_device.paused` for the device with the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device hasn't been added.
+    #[track_caller]
+    pub fn pause(&self, device: &str) {
+        self.expect_device(device).paused();
+        self.flush();
+    }
+
+    /// Sends `ei_device.start_emulating` for the device with the given name, in a receiver
+    /// context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device hasn't been added.
+    #[track_caller]
+    pub fn start_emulating(&mut self, device: &str) {
+        self.state.sequence += 1;
+        self.expect_device(device)
+            .start_emulating(self.state.sequence);
+        self.flush();
+    }
+
+    /// Sends `ei_device.stop_emulating` for the device with the given name, in a receiver
+    /// context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device hasn't been added.
+    #[track_caller]
+    pub fn stop_emulating(&self, device: &str) {
+        self.expect_device(device).stop_emulating();
+        self.flush();
+    }
+
+    /// Disconnects the client with the given reason, and stops handling its messages.
+    ///
+    /// Does nothing if the handshake isn't done, or the connection has already ended.
+    pub fn disconnect(&mut self, reason: DisconnectReason, explanation: Option<&str>) {
+        let Some(connection) = &self.state.connection else {
+            return;
+        };
+        if self.state.disconnected.is_some() {
+            return;
+        }
+        connection.disconnected(reason, explanation);
+        self.state.disconnected = Some((DisconnectInitiator::Server, reason));
+        if let Some(token) = self.token.take() {
+            self.event_loop.handle().remove(token);
+        }
+    }
+
+    /// Returns the side that ended the connection, and the reason, once it has ended.
+    #[must_use]
+    pub fn disconnected(&self) -> Option<(DisconnectInitiator, DisconnectReason)> {
+        self.state.disconnected
+    }
+
+    /// Returns the error that ended the connection, if any.
+    #[must_use]
+    pub fn error(&self) -> Option<&Error> {
+        self.state.error.as_ref()
+    }
+
+    /// Returns every request received so far.
+    ///
+    /// [`EisRequest::Disconnect`] is reported by [`disconnected`](Self::disconnected) instead.
+    #[must_use]
+    pub fn requests(&self) -> &[EisRequest] {
+        &self.state.requests
+    }
+
+    /// Returns every request received so far, and forgets them.
+    pub fn take_requests(&mut self) -> Vec<EisRequest> {
+        std::mem::take(&mut self.state.requests)
+    }
+
+    /// Returns the input received so far by the device with the given name.
+    #[must_use]
+    pub fn input(&self, device: &str) -> Vec<Input> {
+        self.state
+            .requests
+            .iter()
+            .filter(|request| request.device().and_then(request::Device::name) == Some(device))
+            .filter_map(Input::from_request)
+            .collect()
+    }
+
+    /// Asserts that the device with the given name received exactly `expected` so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the input differs.
+    #[track_caller]
+    pub fn assert_input(&self, device: &str, expected: &[Input]) {
+        assert_eq!(self.input(device), expected, "input of device `{device}`");
+    }
+
+    #[track_caller]
+    fn expect_device(&self, name: &str) -> &request::Device {
+        self.device(name)
+            .unwrap_or_else(|| panic!("no device `{name}` has been added"))
+    }
+
+    fn flush(&self) {
+        if let Some(connection) = &self.state.connection {
+            let _ = connection.flush();
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Testing clients and EIS implementations against each other, in-process.
+//!
+//! A [`Loopback`] connects an [`ei::Context`] and an [`eis::Context`] over
+//! [`UnixStream::pair`]. Messages only move from one side to the other when asked, so a test
+//! runs deterministically, without an event loop, timeouts or a compositor.
+//!
+//! After the handshake, a [`Connected`] loopback feeds messages through an
+//! [`EiEventConverter`] and an [`EisRequestConverter`], and has helpers to assert on the
+//! resulting [`EiEvent`]s and [`EisRequest`]s:
+//!
+//! ```
+//! use reis::{ei, event::EiEvent, request::DeviceCapability, testing::Loopback};
+//!
+//! let mut connected = Loopback::new()?.handshake("test", ei::handshake::ContextType::Sender)?;
+//! let _seat = connected.server().add_seat(Some("seat"), DeviceCapability::Pointer.into());
+//! connected.roundtrip()?;
+//!
+//! let EiEvent::SeatAdded(seat) = connected.expect_event() else {
+//!     panic!("expected a seat");
+//! };
+//! assert_eq!(seat.seat.name(), Some("seat"));
+//! connected.assert_no_events();
+//! # Ok::<(), reis::Error>(())
+//! ```
+//!
+//! With the `calloop` feature, the [`mock`] module has an EIS implementation that follows a
+//! script, for testing clients.
+
+use std::{io, os::unix::net::UnixStream};
+
+use crate::{
+    ei, eis,
+    event::{self, EiEvent, EiEventConverter},
+    handshake::{self, EiHandshaker, EisHandshaker},
+    request::{self, EisRequest, EisRequestConverter},
+    Error, PendingRequestResult,
+};
+
+#[cfg(feature = "calloop")]
+pub mod mock;
+
+/// Client and server contexts connected to each other.
+#[derive(Debug)]
+pub struct Loopback {
+    ei: ei::Context,
+    eis: eis::Context,
+}
+
+impl Loopback {
+    /// Creates a client context and a server context, connected to each other.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the socket pair can't be created.
+    pub fn new() -> io::Result<Self> {
+        let (client, server) = UnixStream::pair()?;
+        Ok(Self {
+            ei: ei::Context::new(client)?,
+            eis: eis::Context::new(server)?,
+        })
+    }
+
+    /// Returns the client context.
+    #[must_use]
+    pub fn ei(&self) -> &ei::Context {
+        &self.ei
+    }
+
+    /// Returns the server context.
+    #[must_use]
+    pub fn eis(&self) -> &eis::Context {
+        &self.eis
+    }
+
+    /// Sends the messages buffered by the client, and returns the requests the server parses.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or a message can't be parsed.
+    pub fn deliver_requests(&self) -> Result<Vec<eis::Request>, Error> {
+        let mut requests = Vec::new();
+        while let Some(request) = self.next_request()? {
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+
+    /// Sends the messages buffered by the server, and returns the events the client parses.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or a message can't be parsed.
+    pub fn deliver_events(&self) -> Result<Vec<ei::Event>, Error> {
+        let mut events = Vec::new();
+        while let Some(event) = self.next_event()? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    fn next_request(&self) -> Result<Option<eis::Request>, Error> {
+        self.ei.flush().map_err(io::Error::from)?;
+        self.eis.read()?;
+        self.eis
+            .pending_request()
+            .map(handshake::request_result)
+            .transpose()
+    }
+
+    fn next_event(&self) -> Result<Option<ei::Event>, Error> {
+        self.eis.flush().map_err(io::Error::from)?;
+        self.ei.read()?;
+        self.ei
+            .pending_event()
+            .map(handshake::request_result)
+            .transpose()
+    }
+
+    /// Runs the handshake on both sides, with the default handshakers.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or if either side fails the handshake.
+    pub fn handshake(
+        self,
+        name: &str,
+        context_type: ei::handshake::ContextType,
+    ) -> Result<Connected, Error> {
+        let eis_handshaker = EisHandshaker::new(&self.eis, 1);
+        self.handshake_with(EiHandshaker::new(name, context_type), eis_handshaker)
+    }
+
+    /// Runs the handshake on both sides, with the given handshakers.
+    ///
+    /// `server_handshaker` must have been created for [`Loopback::eis`]. Messages sent after the
+    /// handshake stay buffered for the [`Connected`] loopback.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or if either side fails the handshake. This
+    /// includes an authorizer that rejects the client or defers its decision.
+    pub fn handshake_with(
+        self,
+        mut client_handshaker: EiHandshaker<'_>,
+        mut server_handshaker: EisHandshaker,
+    ) -> Result<Connected, Error> {
+        let mut client_resp = None;
+        let mut server_resp = None;
+        // Stop at the end of each handshake, so later messages are left for the converters
+        let (client_resp, server_resp) = loop {
+            let mut progress = false;
+            while server_resp.is_none() {
+                let Some(request) = self.next_request()? else {
+                    break;
+                };
+                progress = true;
+                server_resp = server_handshaker.handle_request(request)?;
+            }
+            while client_resp.is_none() {
+                let Some(event) = self.next_event()? else {
+                    break;
+                };
+                progress = true;
+                client_resp = client_handshaker.handle_event(event)?;
+            }
+            match (client_resp, server_resp) {
+                (Some(client_resp), Some(server_resp)) => break (client_resp, server_resp),
+                resps => (client_resp, server_resp) = resps,
+            }
+            if !progress {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "handshake stalled with no messages to deliver",
+                )
+                .into());
+            }
+        };
+
+        let client = EiEventConverter::new(&self.ei, client_resp);
+        let server = EisRequestConverter::new(&self.eis, server_resp, 1);
+        server.handle().check_required_interfaces()?;
+        Ok(Connected {
+            loopback: self,
+            client,
+            server,
+        })
+    }
+}
+
+/// Client and server that finished the handshake, with converters for their messages.
+pub struct Connected {
+    loopback: Loopback,
+    client: EiEventConverter,
+    server: EisRequestConverter,
+}
+
+impl std::fmt::Debug for Connected {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Connected")
+            .field("loopback", &self.loopback)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connected {
+    /// Returns the contexts.
+    #[must_use]
+    pub fn loopback(&self) -> &Loopback {
+        &self.loopback
+    }
+
+    /// Returns the client's connection, to bind seats or emulate input.
+    #[must_use]
+    pub fn client(&self) -> &event::Connection {
+        self.client.connection()
+    }
+
+    /// Returns the server's connection, to add seats and devices.
+    #[must_use]
+    pub fn server(&self) -> &request::Connection {
+        self.server.handle()
+    }
+
+    /// Delivers the messages sent by the client, converting them into [`EisRequest`]s.
+    ///
+    /// Returns the number of messages delivered. Like the event sources of this crate, the
+    /// server sends `ei_connection.invalid_object` for messages to unknown objects, and
+    /// disconnects the client on protocol errors.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or a protocol error caused by the client.
+    pub fn dispatch_server(&mut self) -> Result<usize, Error> {
+        let context = &self.loopback.eis;
+        self.loopback.ei.flush().map_err(io::Error::from)?;
+        context.read()?;
+        let mut count = 0;
+        while let Some(result) = context.pending_request() {
+            count += 1;
+            let result = match result {
+                PendingRequestResult::Request(request) => self.server.handle_request(request),
+                PendingRequestResult::ParseError(err) => Err(err.into()),
+                PendingRequestResult::InvalidObject(object_id) => {
+                    let handle = self.server.handle();
+                    handle
+                        .connection()
+                        .invalid_object(handle.last_serial(), object_id);
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                self.server.handle().disconnect_with_error(&err);
+                return Err(err);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Delivers the messages sent by the server, converting them into [`EiEvent`]s.
+    ///
+    /// Returns the number of messages delivered. Messages to unknown objects are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, or a protocol error caused by the server.
+    pub fn dispatch_client(&mut self) -> Result<usize, Error> {
+        let context = &self.loopback.ei;
+        self.loopback.eis.flush().map_err(io::Error::from)?;
+        context.read()?;
+        let mut count = 0;
+        while let Some(result) = context.pending_event() {
+            count += 1;
+            match result {
+                PendingRequestResult::Request(event) => self.client.handle_event(event)?,
+                PendingRequestResult::ParseError(err) => return Err(err.into()),
+                PendingRequestResult::InvalidObject(_) => {}
+            }
+        }
+        Ok(count)
+    }
+
+    /// Delivers messages in both directions until neither side has anything left to send.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if [`dispatch_server`](Self::dispatch_server) or
+    /// [`dispatch_client`](Self::dispatch_client) fails.
+    pub fn roundtrip(&mut self) -> Result<(), Error> {
+        while self.dispatch_server()? + self.dispatch_client()? != 0 {}
+        Ok(())
+    }
+
+    /// Returns the next event converted for the client.
+    pub fn next_event(&mut self) -> Option<EiEvent> {
+        self.client.next_event()
+    }
+
+    /// Returns the next request converted for the server.
+    pub fn next_request(&mut self) -> Option<EisRequest> {
+        self.server.next_request()
+    }
+
+    /// Returns the next event converted for the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no event.
+    #[track_caller]
+    pub fn expect_event(&mut self) -> EiEvent {
+        self.next_event().expect("expected an event")
+    }
+
+    /// Returns the next request converted for the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no request.
+    #[track_caller]
+    pub fn expect_request(&mut self) -> EisRequest {
+        self.next_request().expect("expected a request")
+    }
+
+    /// Asserts that the client has no more events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is an event.
+    #[track_caller]
+    pub fn assert_no_events(&mut self) {
+        if let Some(event) = self.next_event() {
+            panic!("expected no more events, got {event:?}");
+        }
+    }
+
+    /// Asserts that the server has no more requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is a request.
+    #[track_caller]
+    pub fn assert_no_requests(&mut self) {
+        if let Some(request) = self.next_request() {
+            panic!("expected no more requests, got {request:?}");
+        }
+    }
+}
@@ -1,21 +1,51 @@
 //! Module containing [`tokio`] event streams.
 
-// TODO: Handle writable fd too?
-
 use futures_util::{Stream, StreamExt};
 use std::{
     io,
+    os::unix::io::AsRawFd,
     pin::Pin,
     task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::io::{unix::AsyncFd, Interest};
+
+pub use crate::async_shared::AuthorizationHandle;
+pub use crate::handshake::{
+    EiHandshaker, EisHandshakeResp, EisHandshaker, HandshakeError, HandshakeResp,
 };
-use tokio::io::unix::AsyncFd;
+use crate::{async_shared, ei, eis, request, Error, PendingRequestResult};
+
+// `AsyncFd::register_with_interest` is `unsafe`, which this crate forbids. The fd is owned by
+// `inner`, which the `AsyncFd` keeps open until it is dropped.
+#[allow(deprecated)]
+fn async_fd<T: AsRawFd>(inner: T, interest: tokio::io::Interest) -> io::Result<AsyncFd<T>> {
+    AsyncFd::with_interest(inner, interest)
+}
 
-pub use crate::handshake::{HandshakeError, HandshakeResp};
-use crate::{async_shared, ei, Error, PendingRequestResult};
+// Sends messages that a flush left buffered because the socket was full, once it is writable.
+fn poll_flush_pending<T: AsRawFd>(
+    fd: &AsyncFd<T>,
+    context: &mut Context<'_>,
+    has_pending_writes: fn(&T) -> bool,
+    flush: fn(&T) -> rustix::io::Result<()>,
+) -> io::Result<()> {
+    while has_pending_writes(fd.get_ref()) {
+        let Poll::Ready(guard) = fd.poll_write_ready(context) else {
+            break;
+        };
+        let mut guard = guard?;
+        flush(guard.get_inner())?;
+        if has_pending_writes(guard.get_inner()) {
+            guard.clear_ready();
+        }
+    }
+    Ok(())
+}
 
 // XXX make this ei::EventStream?
 /// Stream of `ei::Event`s.
-pub struct EiEventStream(AsyncFd<ei::Context>);
+pub struct EiEventStream(AsyncFd<ei::Context>, async_shared::TaskWaker);
 
 impl EiEventStream {
     /// Creates a new event stream.
@@ -24,7 +54,8 @@ impl EiEventStream {
     ///
     /// Will return `Err` if the underlying async file descriptor registration fails.
     pub fn new(context: ei::Context) -> io::Result<Self> {
-        AsyncFd::with_interest(context, tokio::io::Interest::READABLE).map(Self)
+        let waker = async_shared::TaskWaker::new(&context.0);
+        async_fd(context, Interest::READABLE | Interest::WRITABLE).map(|fd| Self(fd, waker))
     }
 }
 
@@ -35,6 +66,15 @@ impl Stream for EiEventStream {
         mut self: Pin<&mut Self>,
         context: &mut Context<'_>,
     ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.1.register(context.waker());
+        if let Err(err) = poll_flush_pending(
+            &self.0,
+            context,
+            ei::Context::has_pending_writes,
+            ei::Context::flush,
+        ) {
+            return Poll::Ready(Some(Err(err)));
+        }
         // If we already have a pending event, return that
         if let Some(res) = async_shared::poll_pending_event(self.0.get_mut()) {
             return res;
@@ -85,6 +125,28 @@ pub async fn ei_handshake(
     async_shared::ei_handshake(events, name, context_type).await
 }
 
+/// Executes the handshake in async mode, using the given handshaker.
+///
+/// A [timeout](EiHandshaker::with_timeout) requires the time driver of the Tokio runtime to be
+/// enabled.
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+/// out.
+pub async fn ei_handshake_with(
+    events: &mut EiEventStream,
+    handshaker: EiHandshaker<'_>,
+) -> Result<HandshakeResp, Error> {
+    if let Some(timeout) = handshaker.timeout() {
+        tokio::time::timeout(timeout, async_shared::ei_handshake_with(events, handshaker))
+            .await
+            .map_err(|_| HandshakeError::Timeout)?
+    } else {
+        async_shared::ei_handshake_with(events, handshaker).await
+    }
+}
+
 impl ei::Context {
     /// Executes the handshake in async mode.
     ///
@@ -105,3 +167,247 @@ impl ei::Context {
         Ok((connection, stream))
     }
 }
+
+/// Stream of `eis::Context`s accepted by an [`eis::Listener`].
+pub struct EisListenerStream(AsyncFd<eis::Listener>);
+
+impl EisListenerStream {
+    /// Creates a new listener stream.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the underlying async file descriptor registration fails.
+    pub fn new(listener: eis::Listener) -> io::Result<Self> {
+        async_fd(listener, Interest::READABLE).map(Self)
+    }
+}
+
+impl Stream for EisListenerStream {
+    type Item = io::Result<eis::Context>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        loop {
+            let mut guard = match ready!(self.0.poll_read_ready(context)) {
+                Ok(guard) => guard,
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            };
+            match guard.get_inner().accept() {
+                Ok(Some(context)) => return Poll::Ready(Some(Ok(context))),
+                Ok(None) => guard.clear_ready(),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+/// Stream of `eis::Request`s.
+pub struct EisRequestStream(AsyncFd<eis::Context>, async_shared::TaskWaker);
+
+impl EisRequestStream {
+    /// Creates a new request stream.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the underlying async file descriptor registration fails.
+    pub fn new(context: eis::Context) -> io::Result<Self> {
+        let waker = async_shared::TaskWaker::new(&context.0);
+        async_fd(context, Interest::READABLE | Interest::WRITABLE).map(|fd| Self(fd, waker))
+    }
+}
+
+impl Stream for EisRequestStream {
+    type Item = io::Result<PendingRequestResult<eis::Request>>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.1.register(context.waker());
+        if let Err(err) = poll_flush_pending(
+            &self.0,
+            context,
+            eis::Context::has_pending_writes,
+            eis::Context::flush,
+        ) {
+            return Poll::Ready(Some(Err(err)));
+        }
+        // If we already have a pending request, return that
+        if let Some(res) = async_shared::poll_pending_request(self.0.get_mut()) {
+            return res;
+        }
+        match ready!(self.0.poll_read_ready_mut(context)) {
+            Ok(mut guard) => {
+                match guard.get_inner().read() {
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Poll::Ready(None),
+                    Err(err) => Poll::Ready(Some(Err(err))),
+                    Ok(_) => {
+                        // `Backend::read()` reads until `WouldBlock`, EOF, or error
+                        guard.clear_ready();
+                        async_shared::poll_pending_request(guard.get_inner_mut())
+                            .unwrap_or(Poll::Pending)
+                    }
+                }
+            }
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+type KeepaliveTimer = Pin<Box<tokio::time::Sleep>>;
+
+impl async_shared::KeepaliveTimer for KeepaliveTimer {
+    fn new(after: Duration) -> Self {
+        Box::pin(tokio::time::sleep(after))
+    }
+
+    fn reset(&mut self, after: Duration) {
+        self.as_mut().reset(tokio::time::Instant::now() + after);
+    }
+
+    fn poll_expired(&mut self, context: &mut Context<'_>) -> Poll<()> {
+        std::future::Future::poll(self.as_mut(), context)
+    }
+}
+
+/// EIS convert request stream.
+///
+/// If an error occurs, it is returned after sending a protocol error to the client, and the
+/// stream ends.
+pub struct EisConvertRequestStream(
+    async_shared::EisConvertRequestStream<EisRequestStream, KeepaliveTimer>,
+);
+
+impl EisConvertRequestStream {
+    /// Periodically checks that the client is still responsive, like
+    /// `EisRequestSource::with_keepalive` of the `calloop` feature.
+    ///
+    /// The client is pinged with [`Connection::ping`](request::Connection::ping). If it doesn't
+    /// answer within `timeout`, it is disconnected with
+    /// [`DisconnectReason::Error`](eis::connection::DisconnectReason::Error), and the stream
+    /// ends with [`EisRequest::Disconnect`](request::EisRequest::Disconnect). After an answer,
+    /// the next ping is sent after `interval`. Pings are only sent while the stream is polled.
+    ///
+    /// This requires the time driver of the Tokio runtime to be enabled.
+    #[must_use]
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.0.keepalive = Some(async_shared::Keepalive::new(interval, timeout));
+        self
+    }
+}
+
+impl Stream for EisConvertRequestStream {
+    type Item = Result<request::EisRequest, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        context: &mut Context<'_>,
+    ) -> Poll<Option<<Self as Stream>::Item>> {
+        self.0.poll_next_unpin(context)
+    }
+}
+
+/// Executes the server-side handshake in async mode, using the given handshaker.
+///
+/// A [timeout](EisHandshaker::set_timeout) requires the time driver of the Tokio runtime to be
+/// enabled. If the authorizer returns [`Authorization::Defer`], the client is disconnected and
+/// [`HandshakeError::Deferred`] is returned. Use [`eis_handshake_with_authorization`] to resolve
+/// the decision later instead.
+///
+/// [`Authorization::Defer`]: crate::handshake::Authorization::Defer
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+/// out.
+pub async fn eis_handshake_with(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+) -> Result<EisHandshakeResp, Error> {
+    eis_handshake_inner(requests, handshaker, None).await
+}
+
+/// Executes the server-side handshake in async mode, waiting for `authorization` to be resolved
+/// if the authorizer returns [`Authorization::Defer`].
+///
+/// ```no_run
+/// # async fn f(context: reis::eis::Context) -> Result<(), reis::Error> {
+/// use reis::{
+///     handshake::Authorization,
+///     tokio::{
+///         eis_handshake_with_authorization, AuthorizationHandle, EisHandshaker, EisRequestStream,
+///     },
+/// };
+///
+/// let authorization = AuthorizationHandle::new();
+/// let mut handshaker = EisHandshaker::new(&context, 1);
+/// handshaker.set_authorizer({
+///     let authorization = authorization.clone();
+///     move |_info| {
+///         // Ask the user, and call `authorization.resolve()` with the answer
+///         # let _ = &authorization;
+///         Authorization::Defer
+///     }
+/// });
+/// let mut requests = EisRequestStream::new(context.clone())?;
+/// let resp = eis_handshake_with_authorization(&mut requests, handshaker, &authorization).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The time spent waiting counts towards the [timeout](EisHandshaker::set_timeout), if any.
+///
+/// [`Authorization::Defer`]: crate::handshake::Authorization::Defer
+///
+/// # Errors
+///
+/// Will return `Err` if there is an I/O error, a protocol violation, the client is rejected, or
+/// the handshake times out.
+pub async fn eis_handshake_with_authorization(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+    authorization: &AuthorizationHandle,
+) -> Result<EisHandshakeResp, Error> {
+    eis_handshake_inner(requests, handshaker, Some(authorization)).await
+}
+
+async fn eis_handshake_inner(
+    requests: &mut EisRequestStream,
+    handshaker: EisHandshaker,
+    authorization: Option<&AuthorizationHandle>,
+) -> Result<EisHandshakeResp, Error> {
+    let context = requests.0.get_ref().clone();
+    if let Some(timeout) = handshaker.timeout() {
+        tokio::time::timeout(
+            timeout,
+            async_shared::eis_handshake_with(requests, &context, handshaker, authorization),
+        )
+        .await
+        .map_err(|_| HandshakeError::Timeout)?
+    } else {
+        async_shared::eis_handshake_with(requests, &context, handshaker, authorization).await
+    }
+}
+
+impl eis::Context {
+    /// Executes the server-side handshake in async mode.
+    ///
+    /// `handshaker` should be created for this context. See [`eis_handshake_with`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is an I/O error, a protocol violation, or the handshake times
+    /// out.
+    pub async fn handshake_tokio(
+        &self,
+        handshaker: EisHandshaker,
+    ) -> Result<(request::Connection, EisConvertRequestStream), Error> {
+        let mut requests = EisRequestStream::new(self.clone())?;
+        let resp = eis_handshake_with(&mut requests, handshaker).await?;
+        let stream = async_shared::EisConvertRequestStream::new(requests, self, resp)?;
+        let connection = stream.converter.handle().clone();
+        Ok((connection, EisConvertRequestStream(stream)))
+    }
+}
@@ -0,0 +1,96 @@
+// Transports carrying the protocol, which are Unix sockets by default.
+
+use std::{
+    fmt, io,
+    os::unix::{
+        io::{AsFd, BorrowedFd, OwnedFd},
+        net::UnixStream,
+    },
+    sync::Arc,
+};
+
+use rustix::io::IoSlice;
+
+use crate::util;
+
+/// Byte stream carrying the EI protocol, along with file descriptors.
+///
+/// This is implemented for [`UnixStream`], which is what [`ei::Context::new`] and
+/// [`eis::Context::new`] use. Other transports, like a socket in a sandbox or a vsock
+/// connection, can be used with [`ei::Context::with_transport`] and
+/// [`eis::Context::with_transport`].
+///
+/// Operations must not block. The file descriptor returned by [`AsFd`] is polled by the event
+/// sources and streams of this crate, so it must become readable or writable when the
+/// transport does.
+///
+/// [`ei::Context::new`]: crate::ei::Context::new
+/// [`eis::Context::new`]: crate::eis::Context::new
+/// [`ei::Context::with_transport`]: crate::ei::Context::with_transport
+/// [`eis::Context::with_transport`]: crate::eis::Context::with_transport
+pub trait Transport: AsFd + fmt::Debug + Send + Sync + 'static {
+    /// Receives bytes into `buf`, and appends file descriptors that came with them to `fds`.
+    ///
+    /// Returns the number of bytes received, which is 0 at end-of-file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WOULDBLOCK` if no data is available, or any other error from the transport.
+    fn recv(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> rustix::io::Result<usize>;
+
+    /// Sends bytes from `buf`, along with `fds` if any bytes are sent.
+    ///
+    /// Returns the number of bytes sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WOULDBLOCK` if nothing can be sent until the transport is writable, or any
+    /// other error from the transport.
+    fn send(&self, buf: &[u8], fds: &[BorrowedFd<'_>]) -> rustix::io::Result<usize>;
+
+    /// Shuts down the receiving side, so later reads and polls see end-of-file.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from the transport.
+    fn shutdown_read(&self) -> rustix::io::Result<()>;
+}
+
+impl Transport for UnixStream {
+    fn recv(&self, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> rustix::io::Result<usize> {
+        util::recv_with_fds(self, buf, fds)
+    }
+
+    fn send(&self, buf: &[u8], fds: &[BorrowedFd<'_>]) -> rustix::io::Result<usize> {
+        util::send_with_fds(self, &[IoSlice::new(buf)], fds)
+    }
+
+    fn shutdown_read(&self) -> rustix::io::Result<()> {
+        rustix::net::shutdown(self, rustix::net::Shutdown::Read)
+    }
+}
+
+// File descriptor a context is polled on.
+#[derive(Clone, Debug)]
+pub(crate) enum ContextFd {
+    Transport(Arc<dyn Transport>),
+    // For a context without a transport: an epoll instance without any interests, which never
+    // becomes readable or writable.
+    Idle(Arc<OwnedFd>),
+}
+
+impl ContextFd {
+    pub(crate) fn idle() -> io::Result<Self> {
+        let epoll = rustix::event::epoll::create(rustix::event::epoll::CreateFlags::CLOEXEC)?;
+        Ok(Self::Idle(Arc::new(epoll)))
+    }
+}
+
+impl AsFd for ContextFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Transport(transport) => transport.as_fd(),
+            Self::Idle(fd) => fd.as_fd(),
+        }
+    }
+}
@@ -4,7 +4,6 @@ use rustix::{
     net,
 };
 use std::{
-    collections::VecDeque,
     fs, io,
     mem::MaybeUninit,
     ops,
@@ -14,18 +13,11 @@ use std::{
         net::UnixStream,
     },
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-// Panics if iterator isn't as long as `N`
-pub fn array_from_iterator_unchecked<T: Copy + Default, I: Iterator<Item = T>, const N: usize>(
-    mut iter: I,
-) -> [T; N] {
-    let mut arr = [T::default(); N];
-    for i in &mut arr {
-        *i = iter.next().unwrap();
-    }
-    arr
-}
+// Most fds sent or received in one message. Ancillary data of this size is kept on the stack.
+pub const MAX_FDS: usize = 32;
 
 pub fn send_with_fds(
     socket: &UnixStream,
@@ -33,9 +25,24 @@ pub fn send_with_fds(
     buf: &[IoSlice],
     fds: &[BorrowedFd],
 ) -> rustix::io::Result<usize> {
+    if fds.is_empty() {
+        let mut cmsg_buffer = net::SendAncillaryBuffer::default();
+        return retry_on_intr(|| {
+            net::sendmsg(socket, buf, &mut cmsg_buffer, net::SendFlags::NOSIGNAL)
+        });
+    }
     #[allow(clippy::manual_slice_size_calculation)]
-    let mut cmsg_space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(fds.len()))];
-    let mut cmsg_buffer = net::SendAncillaryBuffer::new(&mut cmsg_space);
+    let mut cmsg_space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_FDS))];
+    let mut cmsg_heap;
+    let cmsg_space = if fds.len() <= MAX_FDS {
+        &mut cmsg_space[..]
+    } else {
+        #[allow(clippy::manual_slice_size_calculation)]
+        let len = rustix::cmsg_space!(ScmRights(fds.len()));
+        cmsg_heap = vec![MaybeUninit::uninit(); len];
+        &mut cmsg_heap[..]
+    };
+    let mut cmsg_buffer = net::SendAncillaryBuffer::new(cmsg_space);
     cmsg_buffer.push(net::SendAncillaryMessage::ScmRights(fds));
     retry_on_intr(|| net::sendmsg(socket, buf, &mut cmsg_buffer, net::SendFlags::NOSIGNAL))
 }
@@ -43,12 +50,10 @@ pub fn send_with_fds(
 pub fn recv_with_fds(
     socket: &UnixStream,
     buf: &mut [u8],
-    fds: &mut VecDeque<OwnedFd>,
+    fds: &mut Vec<OwnedFd>,
 ) -> rustix::io::Result<usize> {
-    const MAX_FDS: usize = 32;
-
     #[allow(clippy::manual_slice_size_calculation)]
-    let mut cmsg_space = vec![MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_FDS))];
+    let mut cmsg_space = [MaybeUninit::uninit(); rustix::cmsg_space!(ScmRights(MAX_FDS))];
     let mut cmsg_buffer = net::RecvAncillaryBuffer::new(&mut cmsg_space);
     let response = retry_on_intr(|| {
         net::recvmsg(
@@ -130,14 +135,28 @@ impl LockFile {
     }
 }
 
-pub fn poll_readable<T: AsFd>(fd: &T) -> io::Result<()> {
-    rustix::io::retry_on_intr(|| {
+// Waits until the fd is readable, or writable if `writable` is set. Returns `false` if
+// `timeout` elapsed first.
+pub fn poll_readable<T: AsFd>(
+    fd: &T,
+    writable: bool,
+    timeout: Option<Duration>,
+) -> io::Result<bool> {
+    let timeout = timeout
+        .map(rustix::event::Timespec::try_from)
+        .transpose()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut flags = rustix::event::PollFlags::IN;
+    if writable {
+        flags |= rustix::event::PollFlags::OUT;
+    }
+    let count = rustix::io::retry_on_intr(|| {
         rustix::event::poll(
-            &mut [rustix::event::PollFd::new(fd, rustix::event::PollFlags::IN)],
-            None,
+            &mut [rustix::event::PollFd::new(fd, flags)],
+            timeout.as_ref(),
         )
     })?;
-    Ok(())
+    Ok(count != 0)
 }
 
 // TODO libei has a `eis_clock_set_now_func`
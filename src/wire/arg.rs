@@ -3,24 +3,31 @@
 
 use std::{
     fmt,
-    iter::Extend,
     os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
 };
 
 use super::{ByteStream, ParseError};
 
 /// An argument in an event or a request.
-#[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum Arg<'a> {
+    /// Unsigned 32-bit integer, or enum value.
     Uint32(u32),
+    /// Signed 32-bit integer.
     Int32(i32),
+    /// Unsigned 64-bit integer.
     Uint64(u64),
+    /// Signed 64-bit integer.
     Int64(i64),
+    /// 32-bit floating point number.
     Float(f32),
+    /// File descriptor.
     Fd(BorrowedFd<'a>),
+    /// String, which may be null.
     String(Option<&'a str>),
+    /// ID of an object created by the message.
     NewId(u64),
+    /// ID of an existing object.
     Id(u64),
 }
 
@@ -41,36 +48,40 @@ impl fmt::Display for Arg<'_> {
 }
 
 impl Arg<'_> {
-    pub fn write<T, U>(&self, buf: &mut T, fds: &mut U)
-    where
-        T: Extend<u8>,
-        U: Extend<OwnedFd>,
-    {
+    // Number of bytes written by `write`. Fds are sent out of band, so they take no space.
+    pub(crate) fn wire_len(&self) -> usize {
         match self {
-            Arg::Uint32(value) => buf.extend(value.to_ne_bytes()),
-            Arg::Int32(value) => buf.extend(value.to_ne_bytes()),
+            Arg::Uint32(_) | Arg::Int32(_) | Arg::Float(_) | Arg::String(None) => 4,
+            Arg::Uint64(_) | Arg::Int64(_) | Arg::NewId(_) | Arg::Id(_) => 8,
+            Arg::Fd(_) => 0,
+            // Length, then contents with NUL terminator, padded to multiple of 32 bits
+            Arg::String(Some(value)) => 4 + ((value.len() + 1 + 3) & !3),
+        }
+    }
+
+    pub(crate) fn write(&self, buf: &mut Vec<u8>, fds: &mut Vec<OwnedFd>) {
+        match self {
+            Arg::Uint32(value) => buf.extend_from_slice(&value.to_ne_bytes()),
+            Arg::Int32(value) => buf.extend_from_slice(&value.to_ne_bytes()),
             Arg::Uint64(value) | Arg::NewId(value) | Arg::Id(value) => {
-                buf.extend(value.to_ne_bytes());
+                buf.extend_from_slice(&value.to_ne_bytes());
             }
-            Arg::Int64(value) => buf.extend(value.to_ne_bytes()),
-            Arg::Float(value) => buf.extend(value.to_ne_bytes()),
+            Arg::Int64(value) => buf.extend_from_slice(&value.to_ne_bytes()),
+            Arg::Float(value) => buf.extend_from_slice(&value.to_ne_bytes()),
             // XXX unwrap?
-            Arg::Fd(value) => fds.extend([value.try_clone_to_owned().unwrap()]),
+            Arg::Fd(value) => fds.push(value.try_clone_to_owned().unwrap()),
             Arg::String(None) => {
-                buf.extend(0u32.to_ne_bytes());
+                buf.extend_from_slice(&0u32.to_ne_bytes());
             }
             Arg::String(Some(value)) => {
                 // Write 32-bit length, including NUL
                 let len = value.len() as u32 + 1;
-                buf.extend(len.to_ne_bytes());
+                buf.extend_from_slice(&len.to_ne_bytes());
                 // Write contents of string, as UTF-8
-                buf.extend(value.as_bytes().iter().copied());
-                // Add NUL terminator
-                buf.extend(*b"\0");
-                // Pad to multiple of 32 bits
-                if len % 4 != 0 {
-                    buf.extend((0..4 - (len % 4)).map(|_| b'\0'));
-                }
+                let start = buf.len();
+                buf.extend_from_slice(value.as_bytes());
+                // Add NUL terminator, and pad to multiple of 32 bits
+                buf.resize(start + ((len as usize + 3) & !3), 0);
             }
         }
     }
@@ -155,8 +166,8 @@ impl OwnedArg for Option<String> {
             return Ok(None);
         }
         let bytes = buf.read_n(len as usize - 1)?; // Exclude NUL
-        let string = String::from_utf8(bytes.collect())?;
-        buf.read_n(1)?.next(); // NUL
+        let string = String::from_utf8(bytes.to_vec())?;
+        buf.read_n(1)?; // NUL
         while len % 4 != 0 {
             // Padding
             len += 1;
@@ -1,48 +1,188 @@
 /// Backend
 ///
-/// Handles socket reads/writes, byte/fd buffering, and calls into
+/// Handles transport reads/writes, byte/fd buffering, and calls into
 /// serialization code to send/receive discrete typed message.
 ///
-/// Also implements debug printing to stderr when `REIS_DEBUG` is set.
-use rustix::io::{Errno, IoSlice};
+/// Bytes can also be passed in and out directly, without using the transport.
+///
+/// Also implements debug printing to stderr when `REIS_DEBUG` is set, and passes messages to
+/// a message observer and `tracing`.
+use rustix::io::Errno;
 use std::{
+    array,
     collections::{HashMap, VecDeque},
-    env, io,
-    os::unix::{
-        io::{AsFd, BorrowedFd, OwnedFd},
-        net::UnixStream,
+    env,
+    fmt::{self, Write},
+    io, mem,
+    os::unix::io::{AsFd, BorrowedFd, OwnedFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
     },
-    sync::{Arc, Mutex, Weak},
 };
 
 use crate::{
-    ei, eis, util,
+    capture::CaptureRecorder,
+    ei, eis,
+    observer::{Direction, Message, MessageObserver},
+    util,
     wire::{self, Arg, ByteStream, Header, ParseError},
-    Object,
+    Object, Transport,
 };
 
 // 1 MiB. Matches `max_msglen` in libei
 const MAX_MSGLEN: u32 = 1024 * 1024;
 
-#[derive(Debug, Default)]
-struct Buffer {
-    buf: VecDeque<u8>,
+// Minimum free space at the end of the read buffer for each `recv`
+const READ_CHUNK: usize = 16 * 1024;
+
+// Bytes received from the transport that haven't been parsed yet, in `buf[start..end]`.
+//
+// The transport is read directly into the space after `end`, and messages are parsed from
+// contiguous slices. `buf` is kept initialized, so it's only zeroed when it grows.
+#[derive(Default)]
+struct ReadBuffer {
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
     fds: VecDeque<OwnedFd>,
 }
 
-impl Buffer {
-    fn flush_write(&mut self, socket: &UnixStream) -> rustix::io::Result<()> {
-        // TODO avoid allocation
+impl fmt::Debug for ReadBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadBuffer")
+            .field("buf", &&self.buf[self.start..self.end])
+            .field("fds", &self.fds)
+            .finish()
+    }
+}
+
+impl ReadBuffer {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.start += count;
+        if self.start == self.end {
+            self.start = 0;
+            self.end = 0;
+            // Don't keep the memory of a burst of messages, or of a large message
+            if self.buf.len() > READ_CHUNK {
+                self.buf.truncate(READ_CHUNK);
+                self.buf.shrink_to_fit();
+            }
+        }
+    }
+
+    // Makes room for at least `len` more bytes after `end`
+    fn reserve(&mut self, len: usize) {
+        if self.buf.len() - self.end < len {
+            // Move the start of a partial message to the front before growing the buffer
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+            if self.buf.len() - self.end < len {
+                self.buf.resize(self.end + len, 0);
+            }
+        }
+    }
+
+    fn recv(&mut self, transport: &dyn Transport) -> rustix::io::Result<usize> {
+        self.reserve(READ_CHUNK);
+        let mut fds = Vec::new();
+        let count = transport.recv(&mut self.buf[self.end..], &mut fds)?;
+        self.end += count;
+        self.fds.extend(fds);
+        Ok(count)
+    }
+
+    fn extend(&mut self, bytes: &[u8], fds: impl IntoIterator<Item = OwnedFd>) {
+        self.reserve(bytes.len());
+        self.buf[self.end..self.end + bytes.len()].copy_from_slice(bytes);
+        self.end += bytes.len();
+        self.fds.extend(fds);
+    }
+}
+
+// Serialized messages that haven't been sent yet. The allocation is reused for later
+// messages once they are sent.
+#[derive(Debug, Default)]
+struct WriteBuffer {
+    buf: Vec<u8>,
+    fds: Vec<OwnedFd>,
+    // Maximum bytes buffered at any time
+    limit: Option<usize>,
+    // Set once a message is discarded because of `limit`
+    overflowed: bool,
+    // Set while a flush has left messages buffered, until they are all sent
+    stalled: bool,
+}
+
+impl WriteBuffer {
+    fn flush_write(&mut self, transport: &dyn Transport) -> rustix::io::Result<()> {
+        if self.overflowed {
+            return Err(Errno::NOBUFS);
+        }
         while !self.buf.is_empty() {
-            let (slice1, slice2) = self.buf.as_slices();
-            let iov = &[IoSlice::new(slice1), IoSlice::new(slice2)];
-            let fds: Vec<_> = self.fds.iter().map(|x| x.as_fd()).collect();
-            let written = util::send_with_fds(socket, iov, &fds)?;
-            self.buf.drain(0..written).for_each(|_| {});
+            let written = match self.send(transport) {
+                Ok(written) => written,
+                // Transport is full; the rest is sent once it is writable again
+                #[allow(unreachable_patterns)] // `WOULDBLOCK` and `AGAIN` typically equal
+                Err(Errno::WOULDBLOCK | Errno::AGAIN) => break,
+                Err(err) => return Err(err),
+            };
+            self.buf.drain(..written);
             self.fds.clear();
         }
         Ok(())
     }
+
+    fn send(&self, transport: &dyn Transport) -> rustix::io::Result<usize> {
+        match self.fds.first() {
+            None => transport.send(&self.buf, &[]),
+            // Borrow the fds in an array on the stack, padded with the first fd
+            Some(first) if self.fds.len() <= util::MAX_FDS => {
+                let fds: [BorrowedFd; util::MAX_FDS] =
+                    array::from_fn(|i| self.fds.get(i).unwrap_or(first).as_fd());
+                transport.send(&self.buf, &fds[..self.fds.len()])
+            }
+            Some(_) => {
+                let fds: Vec<_> = self.fds.iter().map(AsFd::as_fd).collect();
+                transport.send(&self.buf, &fds)
+            }
+        }
+    }
+
+    // Returns `false` if `length` more bytes don't fit within the limit.
+    fn reserve(&mut self, length: usize) -> bool {
+        if self
+            .limit
+            .is_some_and(|limit| self.buf.len() + length > limit)
+        {
+            self.overflowed = true;
+            return false;
+        }
+        self.buf.reserve(length);
+        true
+    }
+}
+
+// Called when a flush leaves messages buffered, so the event loop driving the context
+// finishes sending them once the transport is writable.
+pub(crate) struct WriteWaker(Box<dyn Fn() + Send + Sync>);
+
+impl WriteWaker {
+    #[cfg(any(feature = "calloop", feature = "tokio", feature = "async-io"))]
+    pub fn new(wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self(Box::new(wake))
+    }
+}
+
+impl fmt::Debug for WriteWaker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteWaker").finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug)]
@@ -54,12 +194,20 @@ struct BackendState {
 
 #[derive(Debug)]
 struct BackendInner {
-    socket: UnixStream,
+    // `None` if messages are only passed in and out with `receive` and `take_outgoing`
+    transport: Option<Arc<dyn Transport>>,
     client: bool,
+    peer_credentials: Option<eis::PeerCredentials>,
     state: Mutex<BackendState>,
-    read: Mutex<Buffer>,
-    write: Mutex<Buffer>,
+    read: Mutex<ReadBuffer>,
+    write: Mutex<WriteBuffer>,
+    write_waker: Mutex<Option<WriteWaker>>,
     debug: bool,
+    observer: Mutex<Option<Arc<MessageObserver>>>,
+    // Avoids locking `observer` for every message when there is none
+    has_observer: AtomicBool,
+    recorder: Mutex<Option<CaptureRecorder>>,
+    has_recorder: AtomicBool,
 }
 
 // Used for both ei and eis
@@ -90,12 +238,6 @@ impl BackendWeak {
     }
 }
 
-impl AsFd for Backend {
-    fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.socket.as_fd()
-    }
-}
-
 /// Pending message result.
 #[derive(Debug)]
 pub enum PendingRequestResult<T> {
@@ -108,49 +250,60 @@ pub enum PendingRequestResult<T> {
 }
 
 impl Backend {
-    /// Creates a [`Backend`] based on the given `socket`, and whether this is the `client`
-    /// side or not.
-    ///
-    /// # Errors
-    ///
-    /// Will return `Err` if setting the socket to non-blocking mode fails.
-    pub fn new(socket: UnixStream, client: bool) -> io::Result<Self> {
-        socket.set_nonblocking(true)?;
+    /// Creates a [`Backend`] using a non-blocking `transport`, or without one if data is only
+    /// passed in and out directly.
+    pub fn with_transport(
+        transport: Option<Arc<dyn Transport>>,
+        client: bool,
+        peer_credentials: Option<eis::PeerCredentials>,
+    ) -> Self {
         let next_id = if client { 1 } else { 0xff00_0000_0000_0000 };
         let next_peer_id = if client { 0xff00_0000_0000_0000 } else { 1 };
         let backend = Self(Arc::new(BackendInner {
-            socket,
+            transport,
             client,
+            peer_credentials,
             state: Mutex::new(BackendState {
                 next_id,
                 next_peer_id,
                 objects: HashMap::new(),
             }),
-            read: Mutex::new(Buffer::default()),
-            write: Mutex::new(Buffer::default()),
+            read: Mutex::new(ReadBuffer::default()),
+            write: Mutex::new(WriteBuffer::default()),
+            write_waker: Mutex::new(None),
             debug: is_reis_debug(),
+            observer: Mutex::new(None),
+            has_observer: AtomicBool::new(false),
+            recorder: Mutex::new(None),
+            has_recorder: AtomicBool::new(false),
         }));
         let handshake =
             Object::for_new_id(backend.downgrade(), 0, client, "ei_handshake".to_owned(), 1);
         backend.0.state.lock().unwrap().objects.insert(0, handshake);
-        Ok(backend)
+        backend
+    }
+
+    /// Returns the credentials of the client, if this is the server side.
+    pub fn peer_credentials(&self) -> Option<&eis::PeerCredentials> {
+        self.0.peer_credentials.as_ref()
     }
 
     pub(crate) fn downgrade(&self) -> BackendWeak {
         BackendWeak(Arc::downgrade(&self.0))
     }
 
-    /// Reads any pending data on the socket into the backend's internal buffer.
+    /// Reads any pending data on the transport into the backend's internal buffer.
     ///
     /// Returns `UnexpectedEof` if end-of-file is reached.
     pub fn read(&self) -> io::Result<usize> {
+        let Some(transport) = &self.0.transport else {
+            return Ok(0);
+        };
         let mut read = self.0.read.lock().unwrap();
 
-        // TODO read into read.buf with iov?
-        let mut buf = [0; 2048];
         let mut total_count = 0;
         loop {
-            match util::recv_with_fds(&self.0.socket, &mut buf, &mut read.fds) {
+            match read.recv(&**transport) {
                 Ok(0) if total_count == 0 => {
                     return Err(io::Error::new(
                         io::ErrorKind::UnexpectedEof,
@@ -162,7 +315,6 @@ impl Backend {
                     return Ok(total_count);
                 }
                 Ok(count) => {
-                    read.buf.extend(&buf[0..count]);
                     total_count += count;
                 }
                 Err(err) => return Err(err.into()),
@@ -175,44 +327,76 @@ impl Backend {
         &self,
         parse: fn(Object, u32, &mut ByteStream) -> Result<T, ParseError>,
     ) -> Option<PendingRequestResult<T>> {
+        Some(match self.pending_with_header(parse)? {
+            PendingRequestResult::Request((_, message)) => PendingRequestResult::Request(message),
+            PendingRequestResult::ParseError(err) => PendingRequestResult::ParseError(err),
+            PendingRequestResult::InvalidObject(id) => PendingRequestResult::InvalidObject(id),
+        })
+    }
+
+    /// Returns a message that is readily available, along with its header.
+    pub(crate) fn pending_with_header<T: wire::MessageEnum>(
+        &self,
+        parse: fn(Object, u32, &mut ByteStream) -> Result<T, ParseError>,
+    ) -> Option<PendingRequestResult<(Header, T)>> {
         let mut read = self.0.read.lock().unwrap();
-        if read.buf.len() >= 16 {
-            let header_bytes = util::array_from_iterator_unchecked(read.buf.iter().copied());
-            let header = Header::parse(header_bytes);
+        if read.len() >= 16 {
+            let header = Header::parse(read.buf[read.start..read.start + 16].try_into().unwrap());
             if header.length < 16 || header.length > MAX_MSGLEN {
                 return Some(PendingRequestResult::ParseError(ParseError::HeaderLength(
                     header.length,
                 )));
             }
-            if read.buf.len() < header.length as usize {
+            let length = header.length as usize;
+            if read.len() < length {
                 return None;
             }
             if let Some(object) = self.object_for_id(header.object_id) {
-                let read = &mut *read;
-                read.buf.drain(..16); // Remove header
+                let buffer = &mut *read;
+                let payload = &buffer.buf[buffer.start + 16..buffer.start + length];
+                // Interface before parsing, in case the message is a destructor
+                let interface = self
+                    .0
+                    .has_recorder
+                    .load(Ordering::Relaxed)
+                    .then(|| object.interface().to_owned());
+                let fds_before = buffer.fds.len();
                 let mut bytes = ByteStream {
                     backend: self,
-                    bytes: read.buf.drain(..header.length as usize - 16),
-                    fds: &mut read.fds,
+                    bytes: payload,
+                    fds: &mut buffer.fds,
                 };
-                let request = match parse(object, header.opcode, &mut bytes) {
+                let result = parse(object, header.opcode, &mut bytes);
+                let remaining = bytes.bytes.len();
+                if let Some(interface) = interface {
+                    let fds = fds_before - buffer.fds.len();
+                    self.record(Direction::Incoming, &header, Some(&interface), payload, fds);
+                }
+                buffer.consume(length);
+                // The observer may call back into the context
+                drop(read);
+                let request = match result {
                     Ok(request) => request,
                     Err(err) => return Some(PendingRequestResult::ParseError(err)),
                 };
 
-                if bytes.bytes.len() != 0 {
+                if remaining != 0 {
                     return Some(PendingRequestResult::ParseError(ParseError::MessageLength(
-                        header.length + bytes.bytes.len() as u32,
+                        header.length + remaining as u32,
                         header.length,
                     )));
                 }
 
-                if self.0.debug {
-                    self.print_msg(header.object_id, header.opcode, &request.args(), true);
-                }
-                Some(PendingRequestResult::Request(request))
+                self.log_msg(header.object_id, header.opcode, Direction::Incoming, |f| {
+                    request.visit_args(f);
+                });
+                Some(PendingRequestResult::Request((header, request)))
             } else {
-                read.buf.drain(0..header.length as usize);
+                if self.0.has_recorder.load(Ordering::Relaxed) {
+                    let payload = &read.buf[read.start + 16..read.start + length];
+                    self.record(Direction::Incoming, &header, None, payload, 0);
+                }
+                read.consume(length);
                 Some(PendingRequestResult::InvalidObject(header.object_id))
             }
         } else {
@@ -250,6 +434,29 @@ impl Backend {
         Ok(object)
     }
 
+    pub(crate) fn next_peer_id(&self) -> u64 {
+        self.0.state.lock().unwrap().next_peer_id
+    }
+
+    // Objects created by the peer with an ID of at least `id`
+    pub(crate) fn peer_objects_since(&self, id: u64) -> Vec<Object> {
+        let state = self.0.state.lock().unwrap();
+        let mut objects: Vec<_> = state
+            .objects
+            .iter()
+            .filter(|(object_id, _)| **object_id >= id && **object_id < state.next_peer_id)
+            .map(|(_, object)| object.clone())
+            .collect();
+        objects.sort_by_key(Object::id);
+        objects
+    }
+
+    // Adds an object created by the other side of a decoded connection
+    pub(crate) fn insert_object(&self, id: u64, interface: String, version: u32) {
+        let object = Object::for_new_id(self.downgrade(), id, self.0.client, interface, version);
+        self.0.state.lock().unwrap().objects.insert(id, object);
+    }
+
     pub(crate) fn new_peer_interface<T: crate::wire::Interface>(
         &self,
         id: u64,
@@ -272,68 +479,215 @@ impl Backend {
         self.0.state.lock().unwrap().objects.contains_key(&id)
     }
 
-    fn print_msg(&self, object_id: u64, opcode: u32, args: &[Arg], incoming: bool) {
+    pub fn set_message_observer(&self, observer: Option<MessageObserver>) {
+        let mut current = self.0.observer.lock().unwrap();
+        *current = observer.map(Arc::new);
+        self.0
+            .has_observer
+            .store(current.is_some(), Ordering::Relaxed);
+    }
+
+    // Prints the message if `REIS_DEBUG` is set, and passes it to the observer and `tracing`.
+    // `visit_args` calls its argument with each argument of the message.
+    fn log_msg<'a>(
+        &self,
+        object_id: u64,
+        opcode: u32,
+        direction: Direction,
+        visit_args: impl FnOnce(&mut dyn FnMut(Arg<'a>)),
+    ) {
+        let observer = if self.0.has_observer.load(Ordering::Relaxed) {
+            self.0.observer.lock().unwrap().clone()
+        } else {
+            None
+        };
+        #[cfg(feature = "tracing")]
+        let tracing = tracing::enabled!(target: "reis", tracing::Level::TRACE);
+        #[cfg(not(feature = "tracing"))]
+        let tracing = false;
+        if !self.0.debug && !tracing && observer.is_none() {
+            return;
+        }
+
         let object = self.object_for_id(object_id);
         let interface = object.as_ref().map_or("UNKNOWN", |x| x.interface());
-        let op_name = if self.0.client == incoming {
+        let observer = observer.filter(|observer| observer.observes(interface));
+        if !self.0.debug && !tracing && observer.is_none() {
+            return;
+        }
+        let incoming = direction == Direction::Incoming;
+        let name = if self.0.client == incoming {
             ei::Event::op_name(interface, opcode)
         } else {
             eis::Request::op_name(interface, opcode)
         }
         .unwrap_or("UNKNOWN");
-        if incoming {
-            eprint!(" -> ");
+        let mut args = Vec::new();
+        visit_args(&mut |arg| args.push(arg));
+        let message = Message {
+            direction,
+            object_id,
+            interface,
+            opcode,
+            name,
+            args: &args,
+        };
+
+        if self.0.debug {
+            eprintln!("{message}");
         }
-        eprint!("{interface}@{object_id:x}.{op_name}(");
-        let mut first = true;
-        for arg in args {
-            if !first {
-                eprint!(", ");
-            }
-            first = false;
-            eprint!("{arg}");
+        #[cfg(feature = "tracing")]
+        if tracing {
+            tracing::trace!(
+                target: "reis",
+                ?direction,
+                object_id,
+                interface,
+                opcode,
+                name,
+                args = format_args(&args),
+                "{message}"
+            );
+        }
+        if let Some(observer) = observer {
+            observer.notify(&message);
         }
-        eprintln!(")");
     }
 
-    pub fn request(&self, object_id: u64, opcode: u32, args: &[Arg]) {
-        if self.0.debug {
-            self.print_msg(object_id, opcode, args, false);
+    pub fn set_capture_recorder(&self, recorder: Option<CaptureRecorder>) {
+        let mut current = self.0.recorder.lock().unwrap();
+        *current = recorder;
+        self.0
+            .has_recorder
+            .store(current.is_some(), Ordering::Relaxed);
+    }
+
+    fn record(
+        &self,
+        direction: Direction,
+        header: &Header,
+        interface: Option<&str>,
+        payload: &[u8],
+        fds: usize,
+    ) {
+        if let Some(recorder) = &mut *self.0.recorder.lock().unwrap() {
+            recorder.record(self.0.client, direction, header, interface, payload, fds);
         }
+    }
 
-        let mut write = self.0.write.lock().unwrap();
+    pub fn request(&self, object_id: u64, opcode: u32, args: &[Arg]) {
+        self.log_msg(object_id, opcode, Direction::Outgoing, |f| {
+            args.iter().copied().for_each(f);
+        });
 
-        let start_len = write.buf.len();
+        let length = 16 + args.iter().map(Arg::wire_len).sum::<usize>();
+        let header = Header {
+            object_id,
+            length: length as u32,
+            opcode,
+        };
 
-        // Leave space for header
-        write.buf.extend([0; 16]);
+        let interface = if self.0.has_recorder.load(Ordering::Relaxed) {
+            Some(self.object_for_id(object_id))
+        } else {
+            None
+        };
 
-        // Write arguments
+        let mut write = self.0.write.lock().unwrap();
+        let write = &mut *write;
+        if !write.reserve(length) {
+            log::warn!("reis: Write buffer limit exceeded, discarding message");
+            return;
+        }
+        let start = write.buf.len();
+        header.write(&mut write.buf);
         for arg in args {
-            let write = &mut *write;
             arg.write(&mut write.buf, &mut write.fds);
         }
 
-        // Write header now we know the length
-        let header = Header {
-            object_id,
-            length: (write.buf.len() - start_len) as u32,
-            opcode,
-        };
-        for (i, b) in header.as_bytes().enumerate() {
-            write.buf[start_len + i] = b;
+        if let Some(object) = interface {
+            let fds = args.iter().filter(|arg| matches!(arg, Arg::Fd(_))).count();
+            let interface = object.as_ref().map(Object::interface);
+            let payload = &write.buf[start + 16..];
+            self.record(Direction::Outgoing, &header, interface, payload, fds);
         }
     }
 
     /// Sends buffered messages.
+    ///
+    /// If the socket is full, the rest stays buffered, and the write waker is called if it
+    /// wasn't already.
+    /// `ENOBUFS` is returned if a message was discarded because of the write buffer limit.
     pub fn flush(&self) -> rustix::io::Result<()> {
-        self.0.write.lock().unwrap().flush_write(&self.0.socket)
+        let Some(transport) = &self.0.transport else {
+            return Ok(());
+        };
+        let mut write = self.0.write.lock().unwrap();
+        let res = write.flush_write(&**transport);
+        // Only wake once, rather than on every retry while the transport is full
+        let stalled = !write.buf.is_empty();
+        let wake = stalled && !write.stalled;
+        write.stalled = stalled;
+        drop(write);
+        if wake {
+            if let Some(waker) = &*self.0.write_waker.lock().unwrap() {
+                (waker.0)();
+            }
+        }
+        res
+    }
+
+    /// Sets the function called when a flush leaves messages buffered.
+    #[cfg(any(feature = "calloop", feature = "tokio", feature = "async-io"))]
+    pub(crate) fn set_write_waker(&self, waker: Option<WriteWaker>) {
+        *self.0.write_waker.lock().unwrap() = waker;
+    }
+
+    /// Returns `true` if buffered messages haven't been sent yet.
+    pub fn has_pending_writes(&self) -> bool {
+        !self.0.write.lock().unwrap().buf.is_empty()
+    }
+
+    /// Sets how many bytes may be buffered.
+    pub fn set_write_buffer_limit(&self, limit: Option<usize>) {
+        self.0.write.lock().unwrap().limit = limit;
+    }
+
+    /// Adds bytes and fds received from the peer, as if read from the transport.
+    pub fn receive(&self, bytes: &[u8], fds: impl IntoIterator<Item = OwnedFd>) {
+        self.0.read.lock().unwrap().extend(bytes, fds);
     }
 
-    /// Shutdown read end of socket, so all future reads will return EOF
+    /// Moves buffered messages into `bytes` and `fds`, instead of sending them on the
+    /// transport.
+    pub fn take_outgoing(&self, bytes: &mut Vec<u8>, fds: &mut Vec<OwnedFd>) {
+        let mut write = self.0.write.lock().unwrap();
+        if bytes.is_empty() {
+            mem::swap(bytes, &mut write.buf);
+        } else {
+            bytes.append(&mut write.buf);
+        }
+        fds.append(&mut write.fds);
+    }
+
+    /// Shutdown read end of transport, so all future reads will return EOF
     pub(crate) fn shutdown_read(&self) {
-        let _ = self.0.socket.shutdown(std::net::Shutdown::Read);
+        if let Some(transport) = &self.0.transport {
+            let _ = transport.shutdown_read();
+        }
+    }
+}
+
+// Formats arguments as in the `REIS_DEBUG` output
+pub(crate) fn format_args(args: &[Arg<'_>]) -> String {
+    let mut formatted = String::new();
+    for arg in args {
+        if !formatted.is_empty() {
+            formatted.push_str(", ");
+        }
+        let _ = write!(formatted, "{arg}");
     }
+    formatted
 }
 
 fn is_reis_debug() -> bool {
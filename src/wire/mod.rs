@@ -2,20 +2,18 @@
 ///
 /// This is the lowest level component of reis. It provides serialization
 /// and deserialization of the protocol, and uses Rustix to handle socket IO.
-use std::{
-    collections::{self, VecDeque},
-    fmt, iter,
-    os::unix::io::OwnedFd,
-    string::FromUtf8Error,
-};
+use std::{collections::VecDeque, fmt, os::unix::io::OwnedFd, string::FromUtf8Error};
 
 use crate::Object;
 
 mod arg;
-pub(crate) use arg::{Arg, OwnedArg};
+pub use arg::Arg;
+pub(crate) use arg::OwnedArg;
 mod backend;
 pub use backend::PendingRequestResult;
-pub(crate) use backend::{Backend, BackendWeak};
+#[cfg(any(feature = "calloop", feature = "tokio", feature = "async-io"))]
+pub(crate) use backend::WriteWaker;
+pub(crate) use backend::{format_args, Backend, BackendWeak};
 
 #[derive(Debug)]
 pub(crate) struct Header {
@@ -33,12 +31,10 @@ impl Header {
         }
     }
 
-    pub fn as_bytes(&self) -> impl Iterator<Item = u8> {
-        self.object_id
-            .to_ne_bytes()
-            .into_iter()
-            .chain(self.length.to_ne_bytes())
-            .chain(self.opcode.to_ne_bytes())
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.object_id.to_ne_bytes());
+        buf.extend_from_slice(&self.length.to_ne_bytes());
+        buf.extend_from_slice(&self.opcode.to_ne_bytes());
     }
 }
 
@@ -62,12 +58,13 @@ pub trait Interface: crate::private::Sealed {
 }
 
 pub(crate) trait MessageEnum {
-    fn args(&self) -> Vec<Arg<'_>>;
+    // Calls `f` with each argument of the message, for debug printing
+    fn visit_args<'a>(&'a self, f: &mut dyn FnMut(Arg<'a>));
 }
 
 pub(crate) struct ByteStream<'a> {
     pub backend: &'a Backend,
-    pub bytes: std::collections::vec_deque::Drain<'a, u8>,
+    pub bytes: &'a [u8],
     pub fds: &'a mut VecDeque<OwnedFd>,
 }
 
@@ -76,20 +73,18 @@ impl<'a> ByteStream<'a> {
         self.backend
     }
 
-    // TODO: Using impl Iterator ran into lifetime issues
-    fn read_n<'b>(
-        &'b mut self,
-        n: usize,
-    ) -> Result<iter::Take<&'b mut collections::vec_deque::Drain<'a, u8>>, ParseError> {
+    fn read_n(&mut self, n: usize) -> Result<&'a [u8], ParseError> {
         if self.bytes.len() >= n {
-            Ok(self.bytes.by_ref().take(n))
+            let (bytes, rest) = self.bytes.split_at(n);
+            self.bytes = rest;
+            Ok(bytes)
         } else {
             Err(ParseError::EndOfMessage)
         }
     }
 
     fn read<const N: usize>(&mut self) -> Result<[u8; N], ParseError> {
-        Ok(crate::util::array_from_iterator_unchecked(self.read_n(N)?))
+        Ok(self.read_n(N)?.try_into().unwrap())
     }
 
     fn read_fd(&mut self) -> Result<OwnedFd, ParseError> {
@@ -0,0 +1,374 @@
+//! Client and server talking to each other through `reis::testing`.
+
+use std::{
+    os::unix::io::AsFd,
+    sync::{Arc, Mutex},
+};
+
+use reis::{
+    ei, eis,
+    event::{self, DeviceCapability, EiEvent, Region},
+    handshake::{Authorization, EiHandshaker, EisHandshaker, HandshakeError},
+    observer::{Direction, MessageObserver},
+    request::{self, EisRequest, OutOfBoundsPolicy, RequestError},
+    testing::{Connected, Loopback},
+    Arg, Error, PendingRequestResult,
+};
+
+fn connect(context_type: ei::handshake::ContextType) -> Connected {
+    Loopback::new()
+        .unwrap()
+        .handshake("loopback-test", context_type)
+        .unwrap()
+}
+
+// Adds an emulating device with a 100x100 region, returning both sides of it.
+fn add_device(
+    connected: &mut Connected,
+    capability: DeviceCapability,
+) -> (event::Device, request::Device) {
+    let _seat = connected.server().add_seat(Some("seat"), capability.into());
+    connected.roundtrip().unwrap();
+    let EiEvent::SeatAdded(seat) = connected.expect_event() else {
+        panic!("expected a seat");
+    };
+    seat.seat.bind_capabilities(capability.into());
+    connected.roundtrip().unwrap();
+
+    let EisRequest::Bind(bind) = connected.expect_request() else {
+        panic!("expected a bind");
+    };
+    let device = bind.seat.add_device(
+        Some("device"),
+        ei::device::DeviceType::Physical,
+        capability.into(),
+        |device| {
+            device.add_region(Region {
+                x: 0,
+                y: 0,
+                width: 100,
+                height: 100,
+                scale: 1.0,
+                mapping_id: None,
+            });
+        },
+    );
+    device.resumed();
+    connected.roundtrip().unwrap();
+    let EiEvent::DeviceAdded(added) = connected.expect_event() else {
+        panic!("expected a device");
+    };
+    assert!(matches!(
+        connected.expect_event(),
+        EiEvent::DeviceResumed(_)
+    ));
+
+    let serial = connected.client().serial();
+    added.device.device().start_emulating(serial, 1);
+    connected.roundtrip().unwrap();
+    assert!(matches!(
+        connected.expect_request(),
+        EisRequest::DeviceStartEmulating(_)
+    ));
+    (added.device, device)
+}
+
+#[test]
+fn handshake() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    assert_eq!(connected.server().name(), Some("loopback-test"));
+    assert_eq!(
+        connected.server().context_type(),
+        ei::handshake::ContextType::Sender
+    );
+    assert!(connected.server().has_interface("ei_pointer"));
+    connected.roundtrip().unwrap();
+    connected.assert_no_events();
+    connected.assert_no_requests();
+
+    let credentials = connected.server().peer_credentials().unwrap();
+    assert_eq!(credentials.pid, std::process::id() as i32);
+    assert_eq!(credentials.uid, rustix::process::getuid().as_raw());
+}
+
+#[test]
+fn sender_pointer_motion() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let _seat = connected
+        .server()
+        .add_seat(Some("seat"), DeviceCapability::Pointer.into());
+    connected.roundtrip().unwrap();
+
+    let EiEvent::SeatAdded(seat) = connected.expect_event() else {
+        panic!("expected a seat");
+    };
+    assert_eq!(seat.seat.name(), Some("seat"));
+    seat.seat
+        .bind_capabilities(DeviceCapability::Pointer.into());
+    connected.roundtrip().unwrap();
+
+    let EisRequest::Bind(bind) = connected.expect_request() else {
+        panic!("expected a bind");
+    };
+    assert_eq!(bind.capabilities, DeviceCapability::Pointer);
+    let device = bind.seat.add_device(
+        Some("pointer"),
+        ei::device::DeviceType::Virtual,
+        DeviceCapability::Pointer.into(),
+        |_| {},
+    );
+    device.resumed();
+    connected.roundtrip().unwrap();
+
+    let EiEvent::DeviceAdded(added) = connected.expect_event() else {
+        panic!("expected a device");
+    };
+    assert_eq!(added.device.name(), Some("pointer"));
+    assert!(matches!(
+        connected.expect_event(),
+        EiEvent::DeviceResumed(_)
+    ));
+    connected.assert_no_events();
+
+    let serial = connected.client().serial();
+    added.device.device().start_emulating(serial, 1);
+    let pointer = added.device.interface::<ei::Pointer>().unwrap();
+    pointer.motion_relative(1.0, 2.0);
+    added.device.device().frame(serial, 1234);
+    connected.roundtrip().unwrap();
+
+    assert!(matches!(
+        connected.expect_request(),
+        EisRequest::DeviceStartEmulating(_)
+    ));
+    let EisRequest::PointerMotion(motion) = connected.expect_request() else {
+        panic!("expected pointer motion");
+    };
+    assert_eq!((motion.dx, motion.dy, motion.time), (1.0, 2.0, 1234));
+    assert!(matches!(connected.expect_request(), EisRequest::Frame(_)));
+    connected.assert_no_requests();
+}
+
+#[test]
+fn rejected_by_authorizer() {
+    let loopback = Loopback::new().unwrap();
+    let mut server_handshaker = EisHandshaker::new(loopback.eis(), 1);
+    server_handshaker.set_authorizer(|_| Authorization::Reject {
+        reason: reis::eis::connection::DisconnectReason::Disconnected,
+        explanation: None,
+    });
+    let client_handshaker = EiHandshaker::new("loopback-test", ei::handshake::ContextType::Sender);
+    let err = loopback
+        .handshake_with(client_handshaker, server_handshaker)
+        .unwrap_err();
+    assert!(matches!(err, Error::Handshake(HandshakeError::Rejected(_))));
+}
+
+#[test]
+fn without_transport() {
+    let client = ei::Context::without_transport().unwrap();
+    let server = eis::Context::without_transport().unwrap();
+    let _ = (client.as_fd(), server.as_fd());
+    assert_eq!(client.read().unwrap(), 0);
+
+    let mut client_handshaker =
+        EiHandshaker::new("loopback-test", ei::handshake::ContextType::Sender);
+    let mut server_handshaker = EisHandshaker::new(&server, 1);
+    let (mut bytes, mut fds) = (Vec::new(), Vec::new());
+    let (mut client_resp, mut server_resp) = (None, None);
+    for _ in 0..10 {
+        server.flush().unwrap();
+        server.take_outgoing(&mut bytes, &mut fds);
+        client.receive(&bytes, fds.drain(..));
+        bytes.clear();
+        while let Some(PendingRequestResult::Request(event)) = client.pending_event() {
+            client_resp = client_handshaker
+                .handle_event(event)
+                .unwrap()
+                .or(client_resp);
+        }
+
+        client.flush().unwrap();
+        client.take_outgoing(&mut bytes, &mut fds);
+        server.receive(&bytes, fds.drain(..));
+        bytes.clear();
+        while let Some(PendingRequestResult::Request(request)) = server.pending_request() {
+            server_resp = server_handshaker
+                .handle_request(request)
+                .unwrap()
+                .or(server_resp);
+        }
+    }
+    assert!(client_resp.is_some());
+    assert_eq!(server_resp.unwrap().name.as_deref(), Some("loopback-test"));
+    assert!(!client.has_pending_writes() && !server.has_pending_writes());
+}
+
+#[test]
+fn observer_calls_back_into_context() {
+    let loopback = Loopback::new().unwrap();
+    let observed = Arc::new(Mutex::new(Vec::new()));
+    let context = loopback.ei().clone();
+    let messages = observed.clone();
+    loopback
+        .ei()
+        .set_message_observer(Some(MessageObserver::new(move |message| {
+            // Reading takes the lock held while parsing the message
+            context.read().unwrap();
+            let version = matches!(message.args, [Arg::Uint32(1)]);
+            messages
+                .lock()
+                .unwrap()
+                .push((message.direction, message.name.to_owned(), version));
+        })));
+
+    let _handshaker = EisHandshaker::new(loopback.eis(), 1);
+    assert_eq!(loopback.deliver_events().unwrap().len(), 1);
+    assert_eq!(
+        *observed.lock().unwrap(),
+        [(Direction::Incoming, "handshake_version".to_owned(), true)]
+    );
+    loopback.ei().set_message_observer(None);
+}
+
+#[test]
+fn pointer_absolute_outside_region() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (device, _) = add_device(&mut connected, DeviceCapability::PointerAbsolute);
+    let pointer = device.interface::<ei::PointerAbsolute>().unwrap();
+    let serial = connected.client().serial();
+
+    pointer.motion_absolute(150.0, 50.0);
+    device.device().frame(serial, 1);
+    connected.roundtrip().unwrap();
+    connected.assert_no_requests();
+
+    connected
+        .server()
+        .set_out_of_bounds_policy(OutOfBoundsPolicy::Clamp);
+    pointer.motion_absolute(150.0, 50.0);
+    device.device().frame(serial, 2);
+    connected.roundtrip().unwrap();
+    let EisRequest::PointerMotionAbsolute(motion) = connected.expect_request() else {
+        panic!("expected absolute pointer motion");
+    };
+    assert!(motion.dx_absolute < 100.0 && motion.dx_absolute > 99.0);
+    assert_eq!(
+        (motion.dy_absolute, motion.region.map(|region| region.width)),
+        (50.0, Some(100))
+    );
+    assert!(matches!(connected.expect_request(), EisRequest::Frame(_)));
+
+    connected
+        .server()
+        .set_out_of_bounds_policy(OutOfBoundsPolicy::Error);
+    pointer.motion_absolute(150.0, 50.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::PointerOutsideRegion))
+    ));
+}
+
+#[test]
+fn pointer_absolute_nan() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (device, _) = add_device(&mut connected, DeviceCapability::PointerAbsolute);
+    connected
+        .server()
+        .set_out_of_bounds_policy(OutOfBoundsPolicy::Clamp);
+    let pointer = device.interface::<ei::PointerAbsolute>().unwrap();
+    pointer.motion_absolute(f32::NAN, 50.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::InvalidCoordinates))
+    ));
+}
+
+#[test]
+fn touch_nan() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (_device, touchscreen) = touch_device(&mut connected);
+    touchscreen.down(1, 10.0, f32::INFINITY);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::InvalidCoordinates))
+    ));
+
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (_device, touchscreen) = touch_device(&mut connected);
+    touchscreen.down(1, 10.0, 10.0);
+    touchscreen.motion(1, f32::NAN, 10.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::InvalidCoordinates))
+    ));
+}
+
+fn touch_device(connected: &mut Connected) -> (event::Device, ei::Touchscreen) {
+    let (device, _) = add_device(connected, DeviceCapability::Touch);
+    let touchscreen = device.interface::<ei::Touchscreen>().unwrap();
+    (device, touchscreen)
+}
+
+#[test]
+fn touch_unknown_id() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (_device, touchscreen) = touch_device(&mut connected);
+    touchscreen.motion(1, 10.0, 10.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::UnknownTouchId))
+    ));
+}
+
+#[test]
+fn touch_duplicated_down() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (_device, touchscreen) = touch_device(&mut connected);
+    touchscreen.down(1, 10.0, 10.0);
+    touchscreen.down(1, 20.0, 20.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::DuplicatedTouchDown))
+    ));
+}
+
+#[test]
+fn touch_outside_region() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (_device, touchscreen) = touch_device(&mut connected);
+    touchscreen.down(1, 150.0, 10.0);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::TouchOutsideRegion))
+    ));
+}
+
+#[test]
+fn touch_up_after_pause() {
+    let mut connected = connect(ei::handshake::ContextType::Sender);
+    let (device, server_device) = add_device(&mut connected, DeviceCapability::Touch);
+    let touchscreen = device.interface::<ei::Touchscreen>().unwrap();
+    let serial = connected.client().serial();
+    touchscreen.down(1, 10.0, 10.0);
+    touchscreen.down(2, 20.0, 20.0);
+    device.device().frame(serial, 1);
+    connected.roundtrip().unwrap();
+    while connected.next_request().is_some() {}
+
+    // Touches ended by pausing may still be released by the client
+    server_device.paused();
+    touchscreen.up(1);
+    connected.roundtrip().unwrap();
+
+    // Until it starts emulating again
+    server_device.resumed();
+    connected.roundtrip().unwrap();
+    let serial = connected.client().serial();
+    device.device().start_emulating(serial, 2);
+    touchscreen.up(2);
+    assert!(matches!(
+        connected.roundtrip(),
+        Err(Error::Request(RequestError::UnknownTouchId))
+    ));
+}
@@ -0,0 +1,342 @@
+//! Clients talking to the scriptable EIS implementation of `reis::testing::mock`.
+
+use std::{os::unix::net::UnixStream, time::Duration};
+
+use reis::{
+    ei,
+    eis::{self, connection::DisconnectReason},
+    event::{DeviceCapability, EiEvent, EiEventConverter, Region},
+    handshake::EiHandshaker,
+    request::EisRequest,
+    testing::mock::{Input, MockDevice, MockSeat, MockServer},
+    PendingRequestResult,
+};
+
+// Client driven from the same thread as the mock server.
+struct Client {
+    context: ei::Context,
+    converter: EiEventConverter,
+}
+
+impl Client {
+    fn connect(
+        mock_seat: MockSeat,
+        context_type: ei::handshake::ContextType,
+    ) -> (Self, MockServer) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let context = ei::Context::new(client).unwrap();
+        let mut mock = MockServer::new(eis::Context::new(server).unwrap())
+            .unwrap()
+            .with_seat(mock_seat);
+
+        let mut handshaker = EiHandshaker::new("mock-test", context_type);
+        let resp = loop {
+            mock.dispatch(Duration::ZERO).unwrap();
+            context.read().unwrap();
+            let Some(PendingRequestResult::Request(event)) = context.pending_event() else {
+                panic!("handshake stalled");
+            };
+            if let Some(resp) = handshaker.handle_event(event).unwrap() {
+                break resp;
+            }
+            context.flush().unwrap();
+        };
+        let converter = EiEventConverter::new(&context, resp);
+        let mut client = Self { context, converter };
+        client.roundtrip(&mut mock);
+        assert!(mock.connection().is_some());
+        (client, mock)
+    }
+
+    // Delivers messages in both directions until neither side has anything left to send.
+    fn roundtrip(&mut self, mock: &mut MockServer) {
+        loop {
+            self.context.flush().unwrap();
+            mock.dispatch(Duration::ZERO).unwrap();
+            self.context.read().unwrap();
+            let mut count = 0;
+            while let Some(result) = self.context.pending_event() {
+                count += 1;
+                if let PendingRequestResult::Request(event) = result {
+                    self.converter.handle_event(event).unwrap();
+                }
+            }
+            if count == 0 {
+                break;
+            }
+        }
+    }
+
+    fn expect_event(&mut self) -> EiEvent {
+        self.converter.next_event().expect("expected an event")
+    }
+}
+
+fn pointer_seat() -> MockSeat {
+    MockSeat::new(
+        "seat",
+        DeviceCapability::Pointer | DeviceCapability::Keyboard,
+    )
+    .with_device(
+        MockDevice::new(
+            "pointer",
+            ei::device::DeviceType::Virtual,
+            DeviceCapability::Pointer.into(),
+        )
+        .with_region(Region {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale: 1.0,
+            mapping_id: None,
+        }),
+    )
+    .with_device(
+        MockDevice::new(
+            "keyboard",
+            ei::device::DeviceType::Virtual,
+            DeviceCapability::Keyboard.into(),
+        )
+        .with_keymap(ei::keyboard::KeymapType::Xkb, "xkb_keymap {};")
+        .unwrap()
+        .with_paused(),
+    )
+}
+
+#[test]
+fn devices_and_input() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Sender);
+
+    let EiEvent::SeatAdded(seat) = client.expect_event() else {
+        panic!("expected a seat");
+    };
+    assert_eq!(seat.seat.name(), Some("seat"));
+    seat.seat
+        .bind_capabilities(DeviceCapability::Pointer | DeviceCapability::Keyboard);
+    client.roundtrip(&mut mock);
+    assert!(matches!(mock.requests(), [EisRequest::Bind(_)]));
+
+    let EiEvent::DeviceAdded(pointer) = client.expect_event() else {
+        panic!("expected a device");
+    };
+    assert_eq!(pointer.device.name(), Some("pointer"));
+    assert_eq!(pointer.device.regions()[0].width, 1920);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+    let EiEvent::DeviceAdded(keyboard) = client.expect_event() else {
+        panic!("expected a device");
+    };
+    assert_eq!(keyboard.device.name(), Some("keyboard"));
+    let keymap = keyboard.device.keymap().unwrap();
+    assert_eq!(keymap.size as usize, "xkb_keymap {};".len());
+    assert!(client.converter.next_event().is_none());
+
+    mock.resume("keyboard");
+    client.roundtrip(&mut mock);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+
+    let serial = client.converter.connection().serial();
+    pointer.device.device().start_emulating(serial, 1);
+    let ei_pointer = pointer.device.interface::<ei::Pointer>().unwrap();
+    ei_pointer.motion_relative(1.0, 2.0);
+    pointer.device.device().frame(serial, 1234);
+    pointer.device.device().stop_emulating(serial);
+    client.roundtrip(&mut mock);
+
+    mock.assert_input(
+        "pointer",
+        &[
+            Input::StartEmulating,
+            Input::PointerMotion { dx: 1.0, dy: 2.0 },
+            Input::Frame,
+            Input::StopEmulating,
+        ],
+    );
+    mock.assert_input("keyboard", &[]);
+    assert_eq!(mock.take_requests().len(), 5);
+    assert!(mock.requests().is_empty());
+}
+
+#[test]
+fn disconnect_with_reason() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Sender);
+    assert!(matches!(client.expect_event(), EiEvent::SeatAdded(_)));
+
+    mock.disconnect(DisconnectReason::Value, Some("scripted"));
+    assert_eq!(
+        mock.disconnected(),
+        Some((
+            reis::calloop::DisconnectInitiator::Server,
+            DisconnectReason::Value
+        ))
+    );
+    client.roundtrip(&mut mock);
+    assert!(matches!(client.expect_event(), EiEvent::SeatRemoved(_)));
+    let EiEvent::Disconnected(disconnected) = client.expect_event() else {
+        panic!("expected a disconnection");
+    };
+    assert_eq!(disconnected.reason, ei::connection::DisconnectReason::Value);
+    assert_eq!(disconnected.explanation.as_deref(), Some("scripted"));
+
+    // Nothing the client sends afterwards is handled
+    let _callback = client.converter.connection().connection().sync(1);
+    let _ = client.context.flush();
+    mock.dispatch(Duration::ZERO).unwrap();
+    assert!(mock.requests().is_empty());
+    assert!(mock.error().is_none());
+}
+
+#[test]
+fn pause_and_resume() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Sender);
+    let EiEvent::SeatAdded(seat) = client.expect_event() else {
+        panic!("expected a seat");
+    };
+    seat.seat
+        .bind_capabilities(DeviceCapability::Pointer.into());
+    client.roundtrip(&mut mock);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceAdded(_)));
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+
+    mock.pause("pointer");
+    client.roundtrip(&mut mock);
+    let EiEvent::DevicePaused(paused) = client.expect_event() else {
+        panic!("expected the device to be paused");
+    };
+    assert_eq!(paused.device.name(), Some("pointer"));
+    assert!(client.converter.next_event().is_none());
+
+    mock.resume("pointer");
+    client.roundtrip(&mut mock);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+}
+
+#[test]
+fn receiver_start_emulating() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Receiver);
+    let EiEvent::SeatAdded(seat) = client.expect_event() else {
+        panic!("expected a seat");
+    };
+    seat.seat
+        .bind_capabilities(DeviceCapability::Pointer | DeviceCapability::Keyboard);
+    client.roundtrip(&mut mock);
+
+    // The resumed device starts emulating right away, the paused one doesn't
+    assert!(matches!(client.expect_event(), EiEvent::DeviceAdded(_)));
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+    let EiEvent::DeviceStartEmulating(start) = client.expect_event() else {
+        panic!("expected emulation to start");
+    };
+    assert_eq!(start.device.name(), Some("pointer"));
+    assert_eq!(start.sequence, 1);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceAdded(_)));
+    assert!(client.converter.next_event().is_none());
+
+    mock.stop_emulating("pointer");
+    mock.resume("keyboard");
+    mock.start_emulating("keyboard");
+    client.roundtrip(&mut mock);
+    assert!(matches!(
+        client.expect_event(),
+        EiEvent::DeviceStopEmulating(_)
+    ));
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+    let EiEvent::DeviceStartEmulating(start) = client.expect_event() else {
+        panic!("expected emulation to start");
+    };
+    assert_eq!(start.device.name(), Some("keyboard"));
+    assert_eq!(start.sequence, 2);
+}
+
+#[test]
+fn keyboard_input() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Sender);
+    let EiEvent::SeatAdded(seat) = client.expect_event() else {
+        panic!("expected a seat");
+    };
+    seat.seat
+        .bind_capabilities(DeviceCapability::Keyboard.into());
+    client.roundtrip(&mut mock);
+    let EiEvent::DeviceAdded(keyboard) = client.expect_event() else {
+        panic!("expected a device");
+    };
+    mock.resume("keyboard");
+    client.roundtrip(&mut mock);
+    assert!(matches!(client.expect_event(), EiEvent::DeviceResumed(_)));
+
+    let serial = client.converter.connection().serial();
+    keyboard.device.device().start_emulating(serial, 1);
+    let ei_keyboard = keyboard.device.interface::<ei::Keyboard>().unwrap();
+    ei_keyboard.key(30, ei::keyboard::KeyState::Press);
+    keyboard.device.device().frame(serial, 1000);
+    ei_keyboard.key(30, ei::keyboard::KeyState::Released);
+    keyboard.device.device().frame(serial, 2000);
+    client.roundtrip(&mut mock);
+
+    mock.assert_input(
+        "keyboard",
+        &[
+            Input::StartEmulating,
+            Input::KeyboardKey {
+                key: 30,
+                state: eis::keyboard::KeyState::Press,
+            },
+            Input::Frame,
+            Input::KeyboardKey {
+                key: 30,
+                state: eis::keyboard::KeyState::Released,
+            },
+            Input::Frame,
+        ],
+    );
+    mock.assert_input("pointer", &[]);
+}
+
+#[test]
+fn flush_outside_dispatch() {
+    let (mut client, mut mock) =
+        Client::connect(pointer_seat(), ei::handshake::ContextType::Sender);
+    let EiEvent::SeatAdded(seat) = client.expect_event() else {
+        panic!("expected a seat");
+    };
+    seat.seat
+        .bind_capabilities(DeviceCapability::Pointer | DeviceCapability::Keyboard);
+    client.roundtrip(&mut mock);
+    while client.converter.next_event().is_some() {}
+
+    // Fill the socket, so the flushes leave messages buffered.
+    let count = 20_000;
+    for _ in 0..count {
+        mock.pause("pointer");
+        mock.resume("pointer");
+    }
+
+    // Nothing is left to read from the client, so only the wakeup of the stalled flush makes
+    // the mock server wait for the socket to become writable.
+    let mut received = 0;
+    for _ in 0..100 {
+        if received == 2 * count {
+            break;
+        }
+        mock.dispatch(Duration::from_millis(100)).unwrap();
+        client.context.read().unwrap();
+        while let Some(result) = client.context.pending_event() {
+            if let PendingRequestResult::Request(event) = result {
+                client.converter.handle_event(event).unwrap();
+            }
+        }
+        while let Some(event) = client.converter.next_event() {
+            assert!(matches!(
+                event,
+                EiEvent::DevicePaused(_) | EiEvent::DeviceResumed(_)
+            ));
+            received += 1;
+        }
+    }
+    assert_eq!(received, 2 * count);
+}